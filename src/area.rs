@@ -0,0 +1,61 @@
+use crate::{map_jobs, RenderConfig};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/* Samples per job; fixed so the estimate doesn't depend on the thread count */
+const SAMPLES_PER_JOB: u64 = 100_000;
+/* Two-sided 95% quantile of the normal distribution */
+const Z_95: f64 = 1.959964;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Estimate {
+    pub area: f64,
+    /// Half-width of the 95% confidence interval around `area`
+    pub error: f64,
+    pub inside: u64,
+    pub samples: u64,
+}
+
+/// Estimates the area of the part of the set the image covers, in units of the
+/// complex plane, by sampling uniform random pixel positions. Each sample that
+/// reaches max_iter counts the plane area of a pixel at that spot, so views that
+/// stretch the plane unevenly are still measured correctly.
+pub fn estimate(config: &RenderConfig, samples: u64, seed: u64) -> Estimate {
+    let (width, height) = (config.width as f64, config.height as f64);
+    let mut master = StdRng::seed_from_u64(seed);
+    let jobs: Vec<(u64, u64)> = (0..samples)
+        .step_by(SAMPLES_PER_JOB as usize)
+        .map(|start| (master.gen(), SAMPLES_PER_JOB.min(samples - start)))
+        .collect();
+
+    /* Per job: samples inside, sum and sum of squares of the sampled pixel areas */
+    let (inside, sum, sum_sqr) = map_jobs(jobs, config, |(job_seed, count): (u64, u64)| {
+        let mut rng = StdRng::seed_from_u64(job_seed);
+        let (mut inside, mut sum, mut sum_sqr) = (0u64, 0.0f64, 0.0f64);
+        for _ in 0..count {
+            let (x, y) = (rng.gen_range(0.0..width), rng.gen_range(0.0..height));
+            if config.escape(config.subpixel_to_complex(x, y)).in_set {
+                let area = config.subpixel_area(x, y);
+                inside += 1;
+                sum += area;
+                sum_sqr += area * area;
+            }
+        }
+        (inside, sum, sum_sqr)
+    })
+    .into_iter()
+    .fold((0, 0.0, 0.0), |a: (u64, f64, f64), b: (u64, f64, f64)| {
+        (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+    });
+
+    let n = samples.max(1) as f64;
+    let pixels = width * height;
+    let mean = sum / n;
+    let variance = (sum_sqr / n - mean * mean).max(0.0);
+    Estimate {
+        area: pixels * mean,
+        error: Z_95 * pixels * (variance / n).sqrt(),
+        inside,
+        samples,
+    }
+}
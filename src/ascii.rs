@@ -0,0 +1,38 @@
+/* Shades from the fastest escapes to the set */
+const RAMP: &[u8] = b" .:-=+*#%@";
+/* Used when the COLUMNS and LINES environment variables aren't set */
+const DEFAULT_COLUMNS: i32 = 80;
+const DEFAULT_LINES: i32 = 24;
+
+/// Columns and lines of the terminal, as the shell exports them in COLUMNS
+/// and LINES, or 80 x 24.
+pub fn terminal_size() -> (i32, i32) {
+    let read = |name: &str, default: i32| -> i32 {
+        std::env::var(name)
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|v: &i32| *v > 0)
+            .unwrap_or(default)
+    };
+    (
+        read("COLUMNS", DEFAULT_COLUMNS),
+        read("LINES", DEFAULT_LINES),
+    )
+}
+
+/// One line of text per two rows of escape counts, since a character
+/// cell is about twice as tall as it is wide. Each character shades the mean
+/// count of its two pixels along RAMP, ending in the densest for the set at
+/// `max_iter`.
+pub fn render(velocities: &[Vec<i32>], max_iter: i32) -> String {
+    let mut text = String::new();
+    for rows in velocities.chunks(2) {
+        for x in 0..rows[0].len() {
+            let mean: f64 = rows.iter().map(|row| row[x] as f64).sum::<f64>() / rows.len() as f64;
+            let shade = (mean / max_iter as f64 * (RAMP.len() - 1) as f64).round() as usize;
+            text.push(RAMP[shade.min(RAMP.len() - 1)] as char);
+        }
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+use num_rational::Rational64;
+
+use crate::{get_divergence_vel, Formula, RenderConfig, Viewport, C64, ITERMAX};
+
+/* The canonical benchmark scene: seahorse valley at 1920x1080, where the set,
+its boundary and the fast-escaping exterior all take up a good share of the
+image. Changing any of these breaks comparisons with earlier results */
+pub const SCENE_WIDTH: i32 = 1920;
+pub const SCENE_HEIGHT: i32 = 1080;
+pub const SCENE_VIEWPORT: Viewport = Viewport {
+    center: C64::new(-0.7453, 0.1127),
+    zoom: 20.0,
+    rotation: 0.0,
+};
+/* Zoom-1 half height of the scene, the --half-height default */
+const SCENE_HALF_HEIGHT: (i64, i64) = (28, 25);
+
+/// The benchmark scene, with the scheduling and point generation settings
+/// of `config`, so each of them can be measured against the same work.
+pub fn scene(config: &RenderConfig) -> RenderConfig {
+    RenderConfig {
+        width: SCENE_WIDTH,
+        height: SCENE_HEIGHT,
+        threshold: 2.0,
+        formula: Formula::Mandelbrot,
+        exponent: 2.0,
+        viewport: Some(SCENE_VIEWPORT),
+        julia: None,
+        z0: None,
+        smooth: false,
+        max_iter: ITERMAX,
+        interior_max_iter: ITERMAX,
+        half_height: Rational64::new(SCENE_HALF_HEIGHT.0, SCENE_HALF_HEIGHT.1),
+        deep: None,
+        ..config.clone()
+    }
+}
+
+/// Wall-clock times of computing the scene's escape counts, the part every
+/// render shares.
+pub struct Report {
+    pub runs: Vec<Duration>,
+    pub pixels: u64,
+}
+
+impl Report {
+    pub fn best(&self) -> Duration {
+        *self.runs.iter().min().unwrap()
+    }
+
+    pub fn median(&self) -> Duration {
+        let mut runs = self.runs.clone();
+        runs.sort();
+        runs[runs.len() / 2]
+    }
+
+    /// Pixels per second of the fastest run, the one least disturbed by
+    /// whatever else the machine was doing.
+    pub fn throughput(&self) -> f64 {
+        self.pixels as f64 / self.best().as_secs_f64()
+    }
+}
+
+/// Renders the scene `runs` times after one untimed warm-up run.
+pub fn run(config: &RenderConfig, runs: u32) -> Report {
+    let scene = scene(config);
+    get_divergence_vel(&scene);
+    let runs: Vec<Duration> = (0..runs)
+        .map(|_| {
+            let start = Instant::now();
+            get_divergence_vel(&scene);
+            start.elapsed()
+        })
+        .collect();
+    Report {
+        runs,
+        pixels: SCENE_WIDTH as u64 * SCENE_HEIGHT as u64,
+    }
+}
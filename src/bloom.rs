@@ -0,0 +1,75 @@
+use rayon::prelude::*;
+
+use crate::color::MathyColor;
+
+/* Pixels with luma above this (0-255 scale) feed the glow */
+const BLOOM_THRESHOLD: f64 = 180.0;
+/* Blur radius as a fraction of the image height */
+const BLOOM_SIGMA_FRACTION: f64 = 0.004;
+
+fn luma(c: &MathyColor<f64>) -> f64 {
+    0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b
+}
+
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius: i64 = (3.0 * sigma).ceil() as i64;
+    let weights: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+fn blur_pass(
+    src: &[MathyColor<f64>],
+    width: usize,
+    height: usize,
+    kernel: &[f64],
+    horizontal: bool,
+) -> Vec<MathyColor<f64>> {
+    let radius = (kernel.len() / 2) as i64;
+    (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = ((i % width) as i64, (i / width) as i64);
+            let mut acc = MathyColor::new(0.0, 0.0, 0.0);
+            for (k, weight) in kernel.iter().enumerate() {
+                let offset = k as i64 - radius;
+                /* Clamp to the edge so the border doesn't darken */
+                let (sx, sy) = if horizontal {
+                    ((x + offset).clamp(0, width as i64 - 1), y)
+                } else {
+                    (x, (y + offset).clamp(0, height as i64 - 1))
+                };
+                acc = acc + src[sy as usize * width + sx as usize] * *weight;
+            }
+            acc
+        })
+        .collect()
+}
+
+/// Adds a blurred copy of the bright regions back onto `pixels`, scaled by
+/// `intensity`. Channels are clamped to 0-255 so the result can be quantized.
+pub fn apply(pixels: &mut [MathyColor<f64>], width: usize, height: usize, intensity: f64) {
+    let bright: Vec<MathyColor<f64>> = pixels
+        .par_iter()
+        .map(|c| {
+            if luma(c) > BLOOM_THRESHOLD {
+                *c
+            } else {
+                MathyColor::new(0.0, 0.0, 0.0)
+            }
+        })
+        .collect();
+
+    let kernel = gaussian_kernel((height as f64 * BLOOM_SIGMA_FRACTION).max(1.0));
+    let blurred = blur_pass(&bright, width, height, &kernel, true);
+    let blurred = blur_pass(&blurred, width, height, &kernel, false);
+
+    pixels
+        .par_iter_mut()
+        .zip(blurred)
+        .for_each(|(pixel, glow)| {
+            *pixel = (*pixel + glow * intensity).clamp();
+        });
+}
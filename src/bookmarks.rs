@@ -0,0 +1,25 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use mandelbrot::Viewport;
+
+/* Kept in the working directory, next to mandelbrot.toml */
+pub const BOOKMARKS_PATH: &str = "mandelbrot-bookmarks.json";
+
+/// Saved viewports by name, in name order.
+pub type Bookmarks = BTreeMap<String, Viewport>;
+
+/// Reads the bookmarks at `path`; a missing file holds none.
+pub fn load(path: &Path) -> Result<Bookmarks, String> {
+    if !path.exists() {
+        return Ok(Bookmarks::new());
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+    serde_json::from_str(&text).map_err(|e| format!("invalid bookmarks {}: {e}", path.display()))
+}
+
+pub fn save(path: &Path, bookmarks: &Bookmarks) -> Result<(), String> {
+    let text = serde_json::to_string_pretty(bookmarks).unwrap();
+    std::fs::write(path, text + "\n").map_err(|e| format!("couldn't write {}: {e}", path.display()))
+}
@@ -0,0 +1,211 @@
+use image::{ImageBuffer, Rgb};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+use crate::{map_jobs, orbit_from, RenderConfig, C64};
+
+/* Samples are drawn from the square that contains every escaping orbit of interest */
+const SAMPLE_RADIUS: f64 = 2.0;
+/* Samples per job; fixed so the result doesn't depend on the thread count */
+const SAMPLES_PER_JOB: u64 = 100_000;
+/* Cells per side of the importance map over the sample square */
+const IMPORTANCE_GRID: usize = 128;
+/* Probe orbits per side of each importance cell */
+const IMPORTANCE_PROBES: usize = 2;
+/* Weight of a cell none of whose probes contribute, relative to one where all
+do; above zero so every cell keeps being sampled and the image stays unbiased */
+const IMPORTANCE_FLOOR: f64 = 0.05;
+
+/// How the Buddhabrot and Nebulabrot pick the orbits they trace.
+#[derive(Debug, Clone, Copy)]
+pub struct Sampling {
+    /// Random values of c traced per pass
+    pub samples: u64,
+    /// Orbits that escape in fewer steps than this are left out
+    pub min_iter: i32,
+    /// Draw c more often where probe orbits contributed, which is near the
+    /// boundary; each orbit is weighted by how much less likely it was drawn
+    pub importance: bool,
+    pub seed: u64,
+}
+
+/* Cell weights of the importance map in row-major order, with their running
+sums for drawing a cell */
+struct ImportanceMap {
+    cumulative: Vec<f64>,
+    weights: Vec<f64>,
+}
+
+impl ImportanceMap {
+    fn new(config: &RenderConfig, min_iter: i32, max_iter: i32) -> ImportanceMap {
+        let cell: f64 = 2.0 * SAMPLE_RADIUS / IMPORTANCE_GRID as f64;
+        let probes = (IMPORTANCE_PROBES * IMPORTANCE_PROBES) as f64;
+        let cells: Vec<usize> = (0..IMPORTANCE_GRID * IMPORTANCE_GRID).collect();
+        let weights: Vec<f64> = map_jobs(cells, config, |i: usize| {
+            let (cx, cy) = (i % IMPORTANCE_GRID, i / IMPORTANCE_GRID);
+            let contributing = (0..IMPORTANCE_PROBES * IMPORTANCE_PROBES)
+                .filter(|p: &usize| {
+                    let (px, py) = (p % IMPORTANCE_PROBES, p / IMPORTANCE_PROBES);
+                    let offset = |cell_index: usize, probe: usize| {
+                        -SAMPLE_RADIUS
+                            + cell
+                                * (cell_index as f64
+                                    + (probe as f64 + 0.5) / IMPORTANCE_PROBES as f64)
+                    };
+                    let c = C64::new(offset(cx, px), offset(cy, py));
+                    contributes(config, c, min_iter, max_iter).is_some()
+                })
+                .count() as f64;
+            IMPORTANCE_FLOOR + (1.0 - IMPORTANCE_FLOOR) * contributing / probes
+        });
+        let cumulative: Vec<f64> = weights
+            .iter()
+            .scan(0.0, |total: &mut f64, w: &f64| {
+                *total += w;
+                Some(*total)
+            })
+            .collect();
+        ImportanceMap {
+            cumulative,
+            weights,
+        }
+    }
+
+    /* A point drawn in proportion to the cell weights, and the weight that
+    makes its orbit count as one uniform sample on average */
+    fn draw(&self, rng: &mut StdRng) -> (C64, f64) {
+        let total: f64 = *self.cumulative.last().unwrap();
+        let target: f64 = rng.gen_range(0.0..total);
+        let i = self
+            .cumulative
+            .partition_point(|sum: &f64| *sum <= target)
+            .min(self.weights.len() - 1);
+        let cell: f64 = 2.0 * SAMPLE_RADIUS / IMPORTANCE_GRID as f64;
+        let (cx, cy) = ((i % IMPORTANCE_GRID) as f64, (i / IMPORTANCE_GRID) as f64);
+        let c = C64::new(
+            -SAMPLE_RADIUS + cell * (cx + rng.gen::<f64>()),
+            -SAMPLE_RADIUS + cell * (cy + rng.gen::<f64>()),
+        );
+        let mean_weight: f64 = total / self.weights.len() as f64;
+        (c, mean_weight / self.weights[i])
+    }
+}
+
+/* The orbit of c up to its escape, if it escapes in min_iter..=max_iter steps */
+fn contributes(config: &RenderConfig, c: C64, min_iter: i32, max_iter: i32) -> Option<Vec<C64>> {
+    let points = orbit_from(
+        config.z0.unwrap_or(c),
+        c,
+        max_iter,
+        config.threshold,
+        config.formula,
+        config.exponent,
+    );
+    let last = points.last().unwrap();
+    let steps = points.len() as i32 - 1;
+    (last.norm() >= config.threshold && steps >= min_iter).then_some(points)
+}
+
+/// Counts how often escaping orbits pass through each pixel. Only orbits that
+/// escape within `max_iter` steps, and in at least `sampling.min_iter`,
+/// contribute, which is what gives the Buddhabrot its shape. With importance
+/// sampling the counts are weighted, so they are no longer whole numbers.
+///
+/// Samples are split into jobs, each with its own RNG seeded from the seed,
+/// and every rayon worker accumulates into a private grid; the grids are
+/// summed at the end. Single-threaded configs trace every job into one grid. The same seed gives the same image on any number of
+/// threads.
+pub fn density(config: &RenderConfig, sampling: &Sampling, max_iter: i32) -> Vec<f64> {
+    let (width, height) = (config.width as usize, config.height as usize);
+    let samples = sampling.samples;
+    let importance: Option<ImportanceMap> = sampling
+        .importance
+        .then(|| ImportanceMap::new(config, sampling.min_iter, max_iter));
+    let mut master = StdRng::seed_from_u64(sampling.seed);
+    let jobs: Vec<(u64, u64)> = (0..samples)
+        .step_by(SAMPLES_PER_JOB as usize)
+        .map(|start| (master.gen(), SAMPLES_PER_JOB.min(samples - start)))
+        .collect();
+
+    let trace = |mut hits: Vec<f64>, (job_seed, count): (u64, u64)| -> Vec<f64> {
+        let mut rng = StdRng::seed_from_u64(job_seed);
+        for _ in 0..count {
+            let (c, weight) = match &importance {
+                Some(map) => map.draw(&mut rng),
+                None => (
+                    C64::new(
+                        rng.gen_range(-SAMPLE_RADIUS..SAMPLE_RADIUS),
+                        rng.gen_range(-SAMPLE_RADIUS..SAMPLE_RADIUS),
+                    ),
+                    1.0,
+                ),
+            };
+            let Some(points) = contributes(config, c, sampling.min_iter, max_iter) else {
+                continue;
+            };
+            for point in &points[..points.len() - 1] {
+                if let Some((x, y)) = config.complex_to_pixel(*point) {
+                    hits[y as usize * width + x as usize] += weight;
+                }
+            }
+        }
+        hits
+    };
+    if config.single_threaded {
+        return jobs.into_iter().fold(vec![0.0f64; width * height], trace);
+    }
+    jobs.into_par_iter()
+        .fold(|| vec![0.0f64; width * height], trace)
+        .reduce(
+            || vec![0.0f64; width * height],
+            |mut a: Vec<f64>, b: Vec<f64>| {
+                for (x, y) in a.iter_mut().zip(b) {
+                    *x += y;
+                }
+                a
+            },
+        )
+}
+
+fn normalize(hits: &[f64]) -> Vec<f64> {
+    /* The square root keeps the faint outer orbits visible next to the dense core */
+    let max = hits.iter().copied().fold(1.0, f64::max);
+    hits.iter().map(|h| (*h / max).sqrt()).collect()
+}
+
+pub fn render_buddhabrot(
+    config: &RenderConfig,
+    sampling: &Sampling,
+    max_iter: i32,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let level = normalize(&density(config, sampling, max_iter));
+    ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+        let v = (level[(y * config.width as u32 + x) as usize] * 255.0).round() as u8;
+        Rgb([v, v, v])
+    })
+}
+
+/// Renders one Buddhabrot pass per channel, each with its own iteration cap,
+/// and stacks them as red, green and blue.
+pub fn render_nebulabrot(
+    config: &RenderConfig,
+    sampling: &Sampling,
+    max_iters: [i32; 3],
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let channels: Vec<Vec<f64>> = max_iters
+        .iter()
+        .enumerate()
+        .map(|(i, max_iter)| {
+            let pass = Sampling {
+                seed: sampling.seed.wrapping_add(i as u64),
+                ..*sampling
+            };
+            normalize(&density(config, &pass, *max_iter))
+        })
+        .collect();
+    ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+        let i = (y * config.width as u32 + x) as usize;
+        Rgb([0, 1, 2].map(|ch| (channels[ch][i] * 255.0).round() as u8))
+    })
+}
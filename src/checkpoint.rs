@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{get_divergence_rows, Order, RenderConfig};
+
+const MAGIC: &[u8; 4] = b"MBCK";
+/* Magic, config hash, width, height, finished rows */
+const HEADER_LEN: usize = 4 + 8 + 4 + 4 + 4;
+
+/// Escape counts for the whole image, computed `every` rows at a time with the
+/// finished rows saved to `path` after each band. A checkpoint left by an
+/// interrupted run with the same settings is picked up where it stopped; one
+/// from different settings is ignored and overwritten. The file is removed
+/// once the grid is complete.
+pub fn divergence_vel(config: &RenderConfig, path: &Path, every: i32) -> io::Result<Vec<Vec<i32>>> {
+    let hash: u64 = config_hash(config);
+    let mut velocities: Vec<Vec<i32>> = load(path, hash, config).unwrap_or_default();
+    if !velocities.is_empty() {
+        eprintln!(
+            "resuming from {} at row {}",
+            path.display(),
+            velocities.len()
+        );
+    }
+
+    while (velocities.len() as i32) < config.height {
+        let start = velocities.len() as i32;
+        let end = (start + every).min(config.height);
+        velocities.extend(get_divergence_rows(start..end, config));
+        save(path, hash, config, &velocities)?;
+    }
+
+    fs::remove_file(path)?;
+    Ok(velocities)
+}
+
+/* Settings that change the escape counts; chunking and traversal order don't */
+pub fn config_hash(config: &RenderConfig) -> u64 {
+    let neutral = RenderConfig {
+        chunk_rows: None,
+        chunk_pixels: 1,
+        order: Order::RowMajor,
+        single_threaded: false,
+        ..config.clone()
+    };
+    /* FNV-1a, so the hash is the same across builds */
+    format!("{neutral:?}")
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash: u64, byte: u8| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+fn load(path: &Path, hash: u64, config: &RenderConfig) -> Option<Vec<Vec<i32>>> {
+    let bytes = fs::read(path).ok()?;
+    let header = bytes.get(..HEADER_LEN)?;
+    let word = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+    if &header[..4] != MAGIC
+        || u64::from_le_bytes(header[4..12].try_into().unwrap()) != hash
+        || word(12) != config.width as u32
+        || word(16) != config.height as u32
+    {
+        return None;
+    }
+    let rows = word(20) as usize;
+    let width = config.width as usize;
+    let body = bytes.get(HEADER_LEN..HEADER_LEN + rows * width * 4)?;
+    Some(
+        body.chunks_exact(width * 4)
+            .map(|row: &[u8]| {
+                row.chunks_exact(4)
+                    .map(|v: &[u8]| i32::from_le_bytes(v.try_into().unwrap()))
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+fn save(path: &Path, hash: u64, config: &RenderConfig, velocities: &[Vec<i32>]) -> io::Result<()> {
+    /* Written beside the target and renamed, so a crash never leaves half a file */
+    let partial = path.with_extension("partial");
+    let mut file = io::BufWriter::new(fs::File::create(&partial)?);
+    file.write_all(MAGIC)?;
+    file.write_all(&hash.to_le_bytes())?;
+    for word in [config.width, config.height, velocities.len() as i32] {
+        file.write_all(&(word as u32).to_le_bytes())?;
+    }
+    for velocity in velocities.iter().flatten() {
+        file.write_all(&velocity.to_le_bytes())?;
+    }
+    file.into_inner()?.sync_all()?;
+    fs::rename(partial, path)
+}
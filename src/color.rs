@@ -0,0 +1,282 @@
+use clap::ValueEnum;
+use image::Rgb;
+use num_traits::{Float, ToPrimitive};
+
+/* Linear sRGB to CIE XYZ, rows X, Y and Z */
+const SRGB_TO_XYZ: [[f64; 3]; 3] = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.0721750],
+    [0.0193339, 0.1191920, 0.9503041],
+];
+/* Inverse of SRGB_TO_XYZ */
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+/* XYZ of the D65 white point, which sRGB white maps to */
+const D65_WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+/* Where CIELAB's cube root gives way to a line near black */
+const LAB_EPSILON: f64 = 6.0 / 29.0;
+
+/// An RGB color with float channels on the 0-255 scale. Channels are not
+/// clamped by the arithmetic, so sums and lerps can run past the ends until
+/// `clamp` or `unwrap` brings them back. `lerp` comes from the `lerp` crate via
+/// `Add` and `Mul<F>`.
+#[derive(Debug, Copy, Clone)]
+pub struct MathyColor<F> {
+    pub r: F,
+    pub g: F,
+    pub b: F,
+}
+
+impl<F> MathyColor<F>
+where
+    F: Float + ToPrimitive,
+{
+    pub fn new(r: F, g: F, b: F) -> Self {
+        Self { r, g, b }
+    }
+
+    // fn from(other: Rgb<u8>) -> Self {
+    //     Self {
+    //         r: F::from(other.0[0]).unwrap(),
+    //         g: F::from(other.0[1]).unwrap(),
+    //         b: F::from(other.0[2]).unwrap()
+    //     }
+    // }
+
+    pub fn from_ref(other: &Rgb<u8>) -> Self {
+        Self {
+            r: F::from(other.0[0]).unwrap(),
+            g: F::from(other.0[1]).unwrap(),
+            b: F::from(other.0[2]).unwrap(),
+        }
+    }
+
+    /// Decodes a gamma-encoded color into linear light.
+    pub fn to_linear(self, gamma: F) -> Self {
+        let max = F::from(255.0).unwrap();
+        Self::new(
+            (self.r / max).powf(gamma) * max,
+            (self.g / max).powf(gamma) * max,
+            (self.b / max).powf(gamma) * max,
+        )
+    }
+
+    /// Inverse of `to_linear`.
+    pub fn to_gamma(self, gamma: F) -> Self {
+        self.to_linear(gamma.recip())
+    }
+
+    /// Raises each channel, clamped and scaled to [0, 1], to its own exponent
+    /// in `exponents` (red, green, blue).
+    pub fn channel_curves(self, exponents: [F; 3]) -> Self {
+        let max = F::from(255.0).unwrap();
+        let clamped = self.clamp();
+        let [r, g, b] = exponents;
+        Self::new(
+            (clamped.r / max).powf(r) * max,
+            (clamped.g / max).powf(g) * max,
+            (clamped.b / max).powf(b) * max,
+        )
+    }
+
+    /// Per-channel absolute difference from `other`.
+    pub fn abs_diff(self, other: Self) -> Self {
+        Self::new(
+            (self.r - other.r).abs(),
+            (self.g - other.g).abs(),
+            (self.b - other.b).abs(),
+        )
+    }
+
+    /// Each channel clamped to [0, 255].
+    pub fn clamp(self) -> Self {
+        let max = F::from(255.0).unwrap();
+        let channel = |v: F| v.max(F::zero()).min(max);
+        Self::new(channel(self.r), channel(self.g), channel(self.b))
+    }
+
+    /// Decodes sRGB into linear light with the exact piecewise curve.
+    pub fn srgb_to_linear(self) -> Self {
+        self.map_unit(|v: F| {
+            if v <= F::from(0.04045).unwrap() {
+                v / F::from(12.92).unwrap()
+            } else {
+                ((v + F::from(0.055).unwrap()) / F::from(1.055).unwrap())
+                    .powf(F::from(2.4).unwrap())
+            }
+        })
+    }
+
+    /// Inverse of `srgb_to_linear`.
+    pub fn linear_to_srgb(self) -> Self {
+        self.map_unit(|v: F| {
+            if v <= F::from(0.0031308).unwrap() {
+                v * F::from(12.92).unwrap()
+            } else {
+                F::from(1.055).unwrap() * v.powf(F::from(1.0 / 2.4).unwrap())
+                    - F::from(0.055).unwrap()
+            }
+        })
+    }
+
+    /* Applies `f` to each channel scaled to [0, 1] */
+    fn map_unit(self, f: impl Fn(F) -> F) -> Self {
+        let max = F::from(255.0).unwrap();
+        Self::new(
+            f(self.r / max) * max,
+            f(self.g / max) * max,
+            f(self.b / max) * max,
+        )
+    }
+
+    /// Rounds to 8-bit channels, saturating channels outside [0, 255] so
+    /// overshoot from blending or post-processing can't panic. NaN becomes 0.
+    pub fn unwrap(&self) -> Rgb<u8> {
+        let clamped = self.clamp();
+        Rgb([
+            clamped.r.round().to_u8().unwrap(),
+            clamped.g.round().to_u8().unwrap(),
+            clamped.b.round().to_u8().unwrap(),
+        ])
+    }
+}
+
+impl MathyColor<f64> {
+    /// Converts an sRGB color into CIELAB through XYZ, with L in r, a in g and
+    /// b in b. L runs from 0 to 100; a and b are roughly within +-128.
+    pub fn srgb_to_lab(self) -> Self {
+        let linear = self.srgb_to_linear() * (1.0 / 255.0);
+        let [x, y, z] = multiply(SRGB_TO_XYZ, [linear.r, linear.g, linear.b]);
+        let f = |t: f64, white: f64| -> f64 {
+            let t = t / white;
+            if t > LAB_EPSILON.powi(3) {
+                t.cbrt()
+            } else {
+                t / (3.0 * LAB_EPSILON * LAB_EPSILON) + 4.0 / 29.0
+            }
+        };
+        let (fx, fy, fz) = (f(x, D65_WHITE[0]), f(y, D65_WHITE[1]), f(z, D65_WHITE[2]));
+        Self::new(116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+    }
+
+    /// Inverse of `srgb_to_lab`. Lab colors outside the sRGB gamut, which a
+    /// blend of two in-gamut colors can reach, are clamped per channel.
+    pub fn lab_to_srgb(self) -> Self {
+        let fy = (self.r + 16.0) / 116.0;
+        let (fx, fz) = (fy + self.g / 500.0, fy - self.b / 200.0);
+        let f_inv = |t: f64, white: f64| -> f64 {
+            let t = if t > LAB_EPSILON {
+                t.powi(3)
+            } else {
+                3.0 * LAB_EPSILON * LAB_EPSILON * (t - 4.0 / 29.0)
+            };
+            t * white
+        };
+        let xyz = [
+            f_inv(fx, D65_WHITE[0]),
+            f_inv(fy, D65_WHITE[1]),
+            f_inv(fz, D65_WHITE[2]),
+        ];
+        let [r, g, b] = multiply(XYZ_TO_SRGB, xyz);
+        (Self::new(r, g, b) * 255.0).clamp().linear_to_srgb()
+    }
+}
+
+fn multiply(matrix: [[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    matrix.map(|row: [f64; 3]| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+impl<F> std::ops::Add for MathyColor<F>
+where
+    F: Float,
+{
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self::Output {
+        MathyColor::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl<F> std::ops::Mul<F> for MathyColor<F>
+where
+    F: Float,
+{
+    type Output = Self;
+    fn mul(self, rhs: F) -> Self::Output {
+        MathyColor::new(self.r * rhs, self.g * rhs, self.b * rhs)
+    }
+}
+
+impl<F> std::ops::Sub for MathyColor<F>
+where
+    F: Float,
+{
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self::Output {
+        MathyColor::new(self.r - rhs.r, self.g - rhs.g, self.b - rhs.b)
+    }
+}
+
+/// How colors are moved into linear light and back.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transfer {
+    /// A pure power curve with this exponent
+    Gamma(f64),
+    /// The sRGB curve, which is linear near black
+    Srgb,
+    /// Not a curve but CIELAB, see `BlendSpace::Lab`
+    Lab,
+}
+
+impl Transfer {
+    pub fn decode(self, color: MathyColor<f64>) -> MathyColor<f64> {
+        match self {
+            Transfer::Gamma(gamma) => color.to_linear(gamma),
+            Transfer::Srgb => color.srgb_to_linear(),
+            Transfer::Lab => color.srgb_to_lab(),
+        }
+    }
+
+    pub fn encode(self, color: MathyColor<f64>) -> MathyColor<f64> {
+        match self {
+            Transfer::Gamma(gamma) => color.to_gamma(gamma),
+            Transfer::Srgb => color.linear_to_srgb(),
+            Transfer::Lab => color.lab_to_srgb(),
+        }
+    }
+}
+
+/// Where palette stops are blended and supersamples averaged.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendSpace {
+    /// The gamma-encoded sRGB values, as stored
+    Rgb,
+    /// CIELAB through XYZ with a D65 white, which spaces lightness about as the
+    /// eye sees it; its hue lines bend, so blues drift toward purple in a blend
+    Lab,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwrap_saturates_out_of_range_channels() {
+        assert_eq!(
+            MathyColor::new(-40.0, 300.0, 128.4).unwrap(),
+            Rgb([0, 255, 128])
+        );
+        assert_eq!(
+            MathyColor::new(-0.4, 255.4, 1e300).unwrap(),
+            Rgb([0, 255, 255])
+        );
+    }
+
+    #[test]
+    fn unwrap_maps_nan_to_zero() {
+        let nan = f64::NAN;
+        assert_eq!(MathyColor::new(nan, 10.0, nan).unwrap(), Rgb([0, 10, 0]));
+    }
+}
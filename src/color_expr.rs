@@ -0,0 +1,36 @@
+use evalexpr::{ContextWithMutableVariables, HashMapContext, Node, Value};
+
+/// A user-supplied formula mapping escape data to a palette position.
+///
+/// The expression sees `t` (escape count over max_iter), `z` (final |z|) and
+/// `n` (raw escape count). Results are clamped to [0, 1].
+#[derive(Debug, Clone)]
+pub struct ColorExpr {
+    tree: Node,
+}
+
+impl ColorExpr {
+    pub fn parse(src: &str) -> Result<ColorExpr, String> {
+        let expr = ColorExpr {
+            tree: evalexpr::build_operator_tree(src).map_err(|e| e.to_string())?,
+        };
+        /* Evaluate once up front so unknown variables or non-numeric results fail before rendering */
+        expr.eval(0.5, 2.0, 1).map_err(|e| e.to_string())?;
+        Ok(expr)
+    }
+
+    fn eval(&self, t: f64, z: f64, n: i32) -> evalexpr::EvalexprResult<f64> {
+        let mut context = HashMapContext::new();
+        context.set_value("t".into(), Value::Float(t))?;
+        context.set_value("z".into(), Value::Float(z))?;
+        context.set_value("n".into(), Value::Int(n as i64))?;
+        self.tree.eval_number_with_context(&context)
+    }
+
+    pub fn position(&self, t: f64, z: f64, n: i32) -> f64 {
+        match self.eval(t, z, n) {
+            Ok(pos) if !pos.is_nan() => pos.clamp(0.0, 1.0),
+            _ => 0.0,
+        }
+    }
+}
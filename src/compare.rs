@@ -0,0 +1,40 @@
+use image::{ImageBuffer, Rgb};
+
+use crate::tiles::render_region;
+use crate::{flat_map_rows, Palette, RenderConfig};
+
+/* Width of the line drawn between the two halves */
+const DIVIDER_PIXELS: i32 = 2;
+
+/// Renders the left half of the image with the first config and palette and
+/// the right half with the second, then draws a divider down the middle. Both
+/// configs must have the same size; each half shows the pixels its config
+/// would put there in a full render.
+pub fn render(
+    sides: [(&RenderConfig, &Palette); 2],
+    divider: Rgb<u8>,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let [(left, left_palette), (right, right_palette)] = sides;
+    let (width, height) = (left.width, left.height);
+    let split = width / 2;
+    let pixels: Vec<Rgb<u8>> = flat_map_rows(0..height, left, |y: i32| -> Vec<Rgb<u8>> {
+        let mut row = render_region(left, left_palette, 0, y, split, 1);
+        row.extend(render_region(
+            right,
+            right_palette,
+            split,
+            y,
+            width - split,
+            1,
+        ));
+        row
+    });
+
+    let left_edge = split - DIVIDER_PIXELS / 2;
+    ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+        if (left_edge..left_edge + DIVIDER_PIXELS).contains(&(x as i32)) {
+            return divider;
+        }
+        pixels[(y * width as u32 + x) as usize]
+    })
+}
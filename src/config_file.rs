@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, ValueEnum};
+use serde::Deserialize;
+
+use mandelbrot::{parse_complex, Formula, PaletteName, LOCATIONS};
+
+use crate::{parse_gradient, Args, Mode};
+
+/* Read from the working directory when --config isn't given */
+pub const DEFAULT_CONFIG_PATH: &str = "mandelbrot.toml";
+
+/// Render settings loaded from a TOML file. Keys use the same names as the
+/// command-line flags; anything left out keeps the flag's default, and any
+/// flag given on the command line wins over the file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ConfigFile {
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub output: Option<String>,
+    /* A palette name like --palette, or stops like --gradient */
+    palette: Option<String>,
+    threshold: Option<f64>,
+    max_iter: Option<i32>,
+    formula: Option<Formula>,
+    mode: Option<Mode>,
+    location: Option<String>,
+    center: Option<String>,
+    zoom: Option<f64>,
+    rotation: Option<f64>,
+    julia: Option<String>,
+    smooth: Option<bool>,
+    supersample: Option<i32>,
+    /// `[[layer]]` tables, composited in file order with the first at the bottom
+    #[serde(rename = "layer")]
+    pub layers: Vec<LayerSpec>,
+}
+
+/// One `[[layer]]` table. A layer starts from the main settings and replaces
+/// the ones it names.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct LayerSpec {
+    /// From 0 (invisible) to 1 (opaque, the default)
+    pub opacity: Option<f64>,
+    pub transparent_interior: bool,
+    palette: Option<PaletteName>,
+    threshold: Option<f64>,
+    formula: Option<Formula>,
+    exponent: Option<f64>,
+    julia: Option<String>,
+    smooth: Option<bool>,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<ConfigFile, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read {}: {e}", path.display()))?;
+        toml::from_str(&text).map_err(|e| format!("invalid config {}: {e}", path.display()))
+    }
+
+    /// Copies every value from the file into `args` unless that flag was set on
+    /// the command line.
+    pub fn apply(&self, args: &mut Args, matches: &ArgMatches) -> Result<(), String> {
+        let from_cli = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+        macro_rules! fill {
+            ($field:ident) => {
+                if let Some(value) = &self.$field {
+                    if !from_cli(stringify!($field)) {
+                        args.$field = value.clone().into();
+                    }
+                }
+            };
+            ($field:ident, $parse:expr) => {
+                if let Some(value) = &self.$field {
+                    if !from_cli(stringify!($field)) {
+                        args.$field = Some($parse(value)?);
+                    }
+                }
+            };
+        }
+        if let Some(name) = &self.location {
+            if !LOCATIONS.iter().any(|l| l.0 == name) {
+                return Err(format!("unknown location '{name}'"));
+            }
+        }
+        if let Some(palette) = &self.palette {
+            let overridden =
+                from_cli("palette") || from_cli("gradient") || from_cli("palette_file");
+            if !overridden {
+                match PaletteName::from_str(palette, true) {
+                    Ok(name) => args.palette = name,
+                    Err(_) => {
+                        args.gradient = Some(parse_gradient(palette).map_err(|e| {
+                            format!(
+                                "palette '{palette}' is neither a palette name nor a gradient: {e}"
+                            )
+                        })?)
+                    }
+                }
+            }
+        }
+        fill!(threshold);
+        fill!(max_iter);
+        fill!(formula);
+        fill!(mode);
+        fill!(location);
+        fill!(center, |s: &String| parse_complex(s));
+        fill!(zoom);
+        fill!(rotation);
+        fill!(julia, |s: &String| parse_complex(s));
+        fill!(smooth);
+        fill!(supersample);
+        Ok(())
+    }
+}
+
+impl LayerSpec {
+    /// Overwrites `args` with the settings this layer names.
+    pub fn apply(&self, args: &mut Args) -> Result<(), String> {
+        if let Some(opacity) = self.opacity {
+            if !(0.0..=1.0).contains(&opacity) {
+                return Err(format!("layer opacity {opacity} is outside [0, 1]"));
+            }
+        }
+        if let Some(palette) = self.palette {
+            args.palette = palette;
+        }
+        if let Some(threshold) = self.threshold {
+            args.threshold = threshold;
+        }
+        if let Some(formula) = self.formula {
+            args.formula = formula;
+        }
+        if let Some(exponent) = self.exponent {
+            args.exponent = exponent;
+        }
+        if let Some(julia) = &self.julia {
+            args.julia = Some(parse_complex(julia)?);
+        }
+        if let Some(smooth) = self.smooth {
+            args.smooth = smooth;
+        }
+        Ok(())
+    }
+}
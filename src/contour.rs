@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use image::Rgb;
+
+/* A grid edge a contour crosses: (vertical, x, y) for the edge from pixel
+(x, y) to (x + 1, y), or to (x, y + 1) when vertical */
+type Edge = (bool, usize, usize);
+
+/// Outlines of the region whose escape count is at least `level`, found by
+/// marching squares over the grid of pixel centers. Crossings are placed by
+/// linear interpolation between neighbouring counts and given in pixel
+/// coordinates. Closed loops end on their first point; outlines cut by the
+/// image edge stay open.
+pub fn trace(velocities: &[Vec<i32>], level: i32) -> Vec<Vec<(f64, f64)>> {
+    let (width, height) = (velocities[0].len(), velocities.len());
+    /* Halfway between the last count outside and the first inside */
+    let iso: f64 = level as f64 - 0.5;
+    let value = |x: usize, y: usize| velocities[y][x] as f64;
+
+    let mut neighbours: BTreeMap<Edge, Vec<Edge>> = BTreeMap::new();
+    let mut link = |a: Edge, b: Edge| {
+        neighbours.entry(a).or_default().push(b);
+        neighbours.entry(b).or_default().push(a);
+    };
+    for y in 0..height.saturating_sub(1) {
+        for x in 0..width.saturating_sub(1) {
+            let corners = [
+                value(x, y),
+                value(x + 1, y),
+                value(x + 1, y + 1),
+                value(x, y + 1),
+            ];
+            let case = corners
+                .iter()
+                .enumerate()
+                .fold(0, |case, (i, v)| case | (((*v > iso) as usize) << i));
+            let (top, right, bottom, left) = (
+                (false, x, y),
+                (true, x + 1, y),
+                (false, x, y + 1),
+                (true, x, y),
+            );
+            /* Saddles are split the way the cell's average value falls */
+            let center_inside = corners.iter().sum::<f64>() / 4.0 > iso;
+            let segments: &[(Edge, Edge)] = match case {
+                1 | 14 => &[(left, top)],
+                2 | 13 => &[(top, right)],
+                3 | 12 => &[(left, right)],
+                4 | 11 => &[(right, bottom)],
+                6 | 9 => &[(top, bottom)],
+                7 | 8 => &[(bottom, left)],
+                5 if center_inside => &[(top, right), (bottom, left)],
+                10 if !center_inside => &[(top, right), (bottom, left)],
+                5 | 10 => &[(left, top), (right, bottom)],
+                _ => &[],
+            };
+            for (a, b) in segments {
+                link(*a, *b);
+            }
+        }
+    }
+
+    let point = |(vertical, x, y): Edge| -> (f64, f64) {
+        let (x2, y2) = if vertical { (x, y + 1) } else { (x + 1, y) };
+        let t = (iso - value(x, y)) / (value(x2, y2) - value(x, y));
+        /* Pixel centers sit half a pixel in from the corner */
+        let lerp = |a: usize, b: usize| a as f64 + t * (b as f64 - a as f64) + 0.5;
+        (lerp(x, x2), lerp(y, y2))
+    };
+
+    /* Open outlines start from an end, so they come out whole */
+    let mut starts: Vec<Edge> = neighbours
+        .iter()
+        .filter(|(_, n)| n.len() == 1)
+        .map(|(edge, _)| *edge)
+        .collect();
+    starts.extend(neighbours.keys());
+    let mut visited: BTreeSet<Edge> = BTreeSet::new();
+    let mut outlines: Vec<Vec<(f64, f64)>> = Vec::new();
+    for start in starts {
+        if visited.contains(&start) {
+            continue;
+        }
+        let mut outline = vec![point(start)];
+        visited.insert(start);
+        let mut current = start;
+        while let Some(next) = neighbours[&current].iter().find(|n| !visited.contains(n)) {
+            outline.push(point(*next));
+            visited.insert(*next);
+            current = *next;
+        }
+        if outline.len() > 2 && neighbours[&current].contains(&start) {
+            outline.push(outline[0]);
+        }
+        outlines.push(outline);
+    }
+    outlines
+}
+
+/// Writes one stroked path per `(level, color)` in `layers`, each holding
+/// every outline `trace` finds at that level, as an SVG the size of the image.
+pub fn save_svg(
+    path: &str,
+    velocities: &[Vec<i32>],
+    layers: &[(i32, Rgb<u8>)],
+) -> std::io::Result<()> {
+    let (width, height) = (velocities[0].len(), velocities.len());
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}">"#
+    )?;
+    for (level, color) in layers {
+        let [r, g, b] = color.0;
+        write!(
+            out,
+            r##"<path fill="none" stroke="#{r:02x}{g:02x}{b:02x}" stroke-width="1" d=""##
+        )?;
+        for outline in trace(velocities, *level) {
+            for (i, (x, y)) in outline.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                write!(out, "{command}{x:.2} {y:.2}")?;
+            }
+        }
+        writeln!(out, r#""/>"#)?;
+    }
+    writeln!(out, "</svg>")?;
+    out.flush()
+}
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{flat_map_rows, Palette, RenderConfig, STDOUT_PATH};
+use clap::ValueEnum;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{DynamicImage, Frame, ImageBuffer, ImageResult, Rgb};
+use ordered_float::NotNan;
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Png,
+}
+
+/// One frame of the animation: every escaping pixel looks up its palette
+/// position shifted by `phase` and wrapped into [0, 1). Points that never
+/// escape, at the config's max_iter, keep the `interior` color.
+pub fn frame(
+    velocities: &[Vec<i32>],
+    config: &RenderConfig,
+    palette: &Palette,
+    interior: Rgb<u8>,
+    phase: f64,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let (width, height) = (velocities[0].len(), velocities.len());
+    let pixels: Vec<u8> = flat_map_rows(0..height as i32, config, |h: i32| {
+        velocities[h as usize].iter().flat_map(|velocity: &i32| {
+            if *velocity >= config.max_iter {
+                return interior.0;
+            }
+            let position: f64 = (*velocity as f64 / config.max_iter as f64 + phase).rem_euclid(1.0);
+            palette.get_color(NotNan::try_from(position).unwrap()).0
+        })
+    });
+    ImageBuffer::from_raw(width as u32, height as u32, pixels).unwrap()
+}
+
+/// Writes `frames` frames covering one full turn of the palette, starting
+/// at phase `config.color_offset`. The escape counts are computed once by the
+/// caller with `config`, so each frame only costs a lookup per pixel.
+pub fn save(
+    path: &str,
+    velocities: &[Vec<i32>],
+    config: &RenderConfig,
+    palette: &Palette,
+    interior: Rgb<u8>,
+    frames: u32,
+    format: Format,
+) -> ImageResult<()> {
+    let images = (0..frames).map(|i| {
+        frame(
+            velocities,
+            config,
+            palette,
+            interior,
+            config.color_offset + i as f64 / frames as f64,
+        )
+    });
+    write_frames(path, images, format)
+}
+
+/// Writes `images` in order as one looping GIF, next to `path` with a .gif
+/// extension, or as PNG frames numbered `<stem>-0000.png`, `<stem>-0001.png`,
+/// ... A GIF can also go to stdout by passing STDOUT_PATH. Frames are made
+/// as they are written, so only one is held at a time.
+pub fn write_frames<I>(path: &str, images: I, format: Format) -> ImageResult<()>
+where
+    I: Iterator<Item = ImageBuffer<Rgb<u8>, Vec<u8>>>,
+{
+    let path = Path::new(path);
+    match format {
+        Format::Gif => {
+            let out: Box<dyn Write> = if path == Path::new(STDOUT_PATH) {
+                Box::new(std::io::stdout().lock())
+            } else {
+                Box::new(File::create(path.with_extension("gif"))?)
+            };
+            let mut encoder = GifEncoder::new(BufWriter::new(out));
+            encoder.set_repeat(Repeat::Infinite)?;
+            for image in images {
+                encoder.encode_frame(Frame::new(DynamicImage::from(image).into_rgba8()))?;
+            }
+        }
+        Format::Png => {
+            let stem = path.file_stem().unwrap().to_string_lossy();
+            for (i, image) in images.enumerate() {
+                image.save(path.with_file_name(format!("{stem}-{i:04}.png")))?;
+            }
+        }
+    }
+    Ok(())
+}
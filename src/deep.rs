@@ -0,0 +1,268 @@
+use num::{BigInt, Signed};
+use num_complex::Complex;
+use num_traits::{Float, Pow, ToPrimitive};
+
+use crate::{Formula, RenderConfig, Viewport, C64};
+
+/* Pixels closer together than this many ulps of the center's magnitude are
+past what f64 can tell apart */
+const DEEP_PRECISION_ULPS: f64 = 16.0;
+/* Fraction bits kept beyond the pixel spacing, so rounding in the orbit stays
+far below a pixel for a whole orbit. That is the whole precision policy: the
+bits follow the zoom (-log2 of the spacing, plus these), every number of a
+render has the same bits, and products are truncated back to them, so each
+step adds about an ulp of 2^-bits. Those errors grow along the orbit no faster
+than a pixel's offset from the center does, so after n steps they amount to
+about n * 2^-64 pixels */
+const DEEP_GUARD_BITS: u32 = 64;
+/* Digits a decimal center's leading digit may sit from the point, beyond the
+fraction bits. Further out it is either lost below the last bit or far past
+the escape radius, and its power of ten would only cost time and memory */
+const DECIMAL_SLACK_DIGITS: i64 = 300;
+/* Leading bits of a Fixed kept on the way to f64, a few past its mantissa */
+const F64_BITS: u64 = 64;
+/* Fixed values and scales up to 2^this convert to f64 directly */
+const F64_DIRECT_BITS: u64 = 1000;
+
+/// The arithmetic `diverges_in` iterates in, so the same loop runs in f64
+/// or, once a view is deeper than f64 resolves, in `Fixed`.
+pub trait Real: Clone {
+    /// `x` at the precision of `self`.
+    fn like(&self, x: f64) -> Self;
+    /// The nearest f64, for the parts of a render that stay in f64.
+    fn to_f64(&self) -> f64;
+    fn add(&self, other: &Self) -> Self;
+    fn sub(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn abs(&self) -> Self;
+    fn neg(&self) -> Self;
+}
+
+impl Real for f64 {
+    fn like(&self, x: f64) -> f64 {
+        x
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn add(&self, other: &f64) -> f64 {
+        self + other
+    }
+
+    fn sub(&self, other: &f64) -> f64 {
+        self - other
+    }
+
+    fn mul(&self, other: &f64) -> f64 {
+        self * other
+    }
+
+    fn abs(&self) -> f64 {
+        f64::abs(*self)
+    }
+
+    fn neg(&self) -> f64 {
+        -self
+    }
+}
+
+/// A real number as a BigInt scaled by 2^bits. Numbers that meet in
+/// arithmetic have the same bits, those of the `Deep` they came from.
+#[derive(Debug, Clone)]
+pub struct Fixed {
+    value: BigInt,
+    bits: u32,
+}
+
+impl Fixed {
+    fn new(value: BigInt, bits: u32) -> Fixed {
+        Fixed { value, bits }
+    }
+
+    /// Whether the number is too large for any finite f64.
+    pub fn exceeds_f64(&self) -> bool {
+        self.value.bits() > self.bits as u64 + f64::MAX_EXP as u64
+    }
+}
+
+impl Real for Fixed {
+    fn like(&self, x: f64) -> Fixed {
+        Fixed::new(from_f64(x, self.bits), self.bits)
+    }
+
+    fn to_f64(&self) -> f64 {
+        if self.value.bits() <= F64_DIRECT_BITS && self.bits as u64 <= F64_DIRECT_BITS {
+            return self.value.to_f64().unwrap() * 2f64.powi(-(self.bits as i32));
+        }
+        /* Only the leading bits matter, and shifting the rest out first keeps
+        long fractions within f64's exponent range */
+        let shift: u32 = (self.value.bits().saturating_sub(F64_BITS) as u32).min(self.bits);
+        let leading: f64 = (&self.value >> shift as usize).to_f64().unwrap();
+        leading * 2f64.powi(-((self.bits - shift) as i32))
+    }
+
+    fn add(&self, other: &Fixed) -> Fixed {
+        Fixed::new(&self.value + &other.value, self.bits)
+    }
+
+    fn sub(&self, other: &Fixed) -> Fixed {
+        Fixed::new(&self.value - &other.value, self.bits)
+    }
+
+    fn mul(&self, other: &Fixed) -> Fixed {
+        Fixed::new(
+            (&self.value * &other.value) >> self.bits as usize,
+            self.bits,
+        )
+    }
+
+    fn abs(&self) -> Fixed {
+        Fixed::new(self.value.abs(), self.bits)
+    }
+
+    fn neg(&self) -> Fixed {
+        Fixed::new(-&self.value, self.bits)
+    }
+}
+
+/// The fixed-point center of a view deeper than f64 resolves. Each pixel's
+/// point is the exact center plus its f64 offset from it, which only needs
+/// f64 precision relative to the pixel spacing.
+#[derive(Debug)]
+pub struct Deep {
+    center: Complex<Fixed>,
+    /* The f64 center the exact one stands in for */
+    origin: C64,
+}
+
+impl Deep {
+    /// The fixed-point setup for `config`, or None if f64 is precise enough or
+    /// the formula only runs in f64. `center_re` and `center_im` replace the
+    /// viewport's f64 center parts with all their decimal digits, and are the
+    /// only source of errors. The precision follows the zoom: enough bits for
+    /// the pixel spacing plus DEEP_GUARD_BITS.
+    pub fn new(
+        config: &RenderConfig,
+        center_re: Option<&str>,
+        center_im: Option<&str>,
+    ) -> Result<Option<Deep>, String> {
+        let Some(viewport) = config.viewport else {
+            return Ok(None);
+        };
+        if !beyond_f64(config) || !supported(config) {
+            return Ok(None);
+        }
+        let bits: u32 = (-spacing(config, &viewport).log2()).ceil() as u32 + DEEP_GUARD_BITS;
+        let part = |digits: Option<&str>, value: f64| -> Result<Fixed, String> {
+            let value: BigInt = match digits {
+                Some(digits) => from_decimal(digits, bits)?,
+                None => from_f64(value, bits),
+            };
+            Ok(Fixed::new(value, bits))
+        };
+        Ok(Some(Deep {
+            center: Complex::new(
+                part(center_re, viewport.center.re)?,
+                part(center_im, viewport.center.im)?,
+            ),
+            origin: viewport.center,
+        }))
+    }
+
+    /// Fraction bits of the fixed-point numbers.
+    pub fn bits(&self) -> u32 {
+        self.center.re.bits
+    }
+
+    /// The f64 center of the view this was made for.
+    pub fn origin(&self) -> C64 {
+        self.origin
+    }
+
+    /// The exact point `offset` away from the center.
+    pub fn point(&self, offset: C64) -> Complex<Fixed> {
+        Complex::new(
+            self.center.re.add(&self.center.re.like(offset.re)),
+            self.center.im.add(&self.center.im.like(offset.im)),
+        )
+    }
+}
+
+/// Whether neighbouring pixels of `config` are closer together than f64 can
+/// tell apart around its center.
+pub fn beyond_f64(config: &RenderConfig) -> bool {
+    config.viewport.is_some_and(|viewport| {
+        spacing(config, &viewport)
+            < DEEP_PRECISION_ULPS * f64::EPSILON * viewport.center.norm().max(1.0)
+    })
+}
+
+/// Whether `config`'s formula runs in fixed point: every one but Multibrot
+/// with a fractional or negative exponent, which needs f64 powers.
+pub fn supported(config: &RenderConfig) -> bool {
+    config.formula != Formula::Multibrot
+        || (config.exponent.fract() == 0.0 && (1.0..=i32::MAX as f64).contains(&config.exponent))
+}
+
+/* Plane distance between neighbouring pixels */
+fn spacing(config: &RenderConfig, viewport: &Viewport) -> f64 {
+    2.0 * config.half_height.to_f64().unwrap() / viewport.zoom / config.height as f64
+}
+
+/* x scaled by 2^bits, exact down to the last bit kept */
+fn from_f64(x: f64, bits: u32) -> BigInt {
+    let (mantissa, exponent, sign) = x.integer_decode();
+    let shift: i64 = exponent as i64 + bits as i64;
+    let magnitude: BigInt = if shift >= 0 {
+        BigInt::from(mantissa) << shift as usize
+    } else {
+        BigInt::from(mantissa) >> (-shift) as usize
+    };
+    if sign < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/* A decimal accepted by `parse_decimal`, scaled by 2^bits */
+fn from_decimal(s: &str, bits: u32) -> Result<BigInt, String> {
+    let text = s.trim();
+    let negative = text.starts_with('-');
+    let text = text.trim_start_matches(['+', '-']);
+    let (mantissa, exponent) = match text.split_once(['e', 'E']) {
+        Some((m, e)) => (
+            m,
+            e.parse::<i64>()
+                .map_err(|_| format!("the exponent of '{s}' is out of range"))?,
+        ),
+        None => (text, 0),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits: String = format!("{int}{frac}");
+    let digits: BigInt = all_digits
+        .parse()
+        .map_err(|_| format!("'{s}' is not a decimal number"))?;
+    let exponent: i64 = exponent
+        .checked_sub(frac.len() as i64)
+        .ok_or_else(|| format!("the exponent of '{s}' is out of range"))?;
+    let significant: usize = all_digits.trim_start_matches('0').len();
+    if significant > 0 {
+        let leading: i128 = exponent as i128 + significant as i128 - 1;
+        if leading.abs() > (bits as i64 + DECIMAL_SLACK_DIGITS) as i128 {
+            return Err(format!(
+                "'{s}' is of order 1e{leading}, too far from the escape radius for \
+                 {bits} bits of fixed point"
+            ));
+        }
+    }
+    let ten = BigInt::from(10);
+    let magnitude: BigInt = if exponent >= 0 {
+        (digits * ten.pow(exponent as u64)) << bits as usize
+    } else {
+        (digits << bits as usize) / ten.pow((-exponent) as u64)
+    };
+    Ok(if negative { -magnitude } else { magnitude })
+}
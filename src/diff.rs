@@ -0,0 +1,53 @@
+use image::{ImageBuffer, Rgb};
+
+use crate::color::MathyColor;
+use crate::tiles::render_region;
+use crate::{flat_map_rows, Palette, RenderConfig};
+
+/// Largest and mean per-channel difference between two renders, on the 0-255
+/// scale.
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub max: f64,
+    pub mean: f64,
+    /// Pixels where any channel differs
+    pub changed: u64,
+}
+
+/// Renders both configs in full and returns their per-pixel absolute
+/// difference scaled by `amplify`, along with the unscaled summary. Both
+/// configs must have the same size.
+pub fn render(
+    sides: [(&RenderConfig, &Palette); 2],
+    amplify: f64,
+) -> (ImageBuffer<Rgb<u8>, Vec<u8>>, Summary) {
+    let [(left, left_palette), (right, right_palette)] = sides;
+    let (width, height) = (left.width, left.height);
+    let differences: Vec<MathyColor<f64>> = flat_map_rows(0..height, left, |y: i32| {
+        let a = render_region(left, left_palette, 0, y, width, 1);
+        let b = render_region(right, right_palette, 0, y, width, 1);
+        a.into_iter()
+            .zip(b)
+            .map(|(a, b): (Rgb<u8>, Rgb<u8>)| {
+                MathyColor::from_ref(&a).abs_diff(MathyColor::from_ref(&b))
+            })
+            .collect::<Vec<_>>()
+    });
+
+    let channels = |d: &MathyColor<f64>| [d.r, d.g, d.b];
+    let summary = Summary {
+        max: differences.iter().flat_map(channels).fold(0.0, f64::max),
+        mean: differences.iter().flat_map(channels).sum::<f64>()
+            / (differences.len().max(1) * 3) as f64,
+        changed: differences
+            .iter()
+            .filter(|d| channels(d).iter().any(|v| *v > 0.0))
+            .count() as u64,
+    };
+    let imgbuf = ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+        (differences[(y * width as u32 + x) as usize] * amplify)
+            .clamp()
+            .unwrap()
+    });
+    (imgbuf, summary)
+}
@@ -0,0 +1,78 @@
+use lerp::Lerp;
+use ordered_float::NotNan;
+
+use crate::color::MathyColor;
+use crate::{flat_map_rows, EscapeResult, Formula, Palette, RenderConfig, C64};
+
+/* Pixels whose center escapes within this many pixel widths of the set are supersampled */
+const ADAPTIVE_TRIGGER_PIXELS: f64 = 1.0;
+
+/// Estimated distance from `point` to the set boundary, from the orbit and its
+/// derivative `dz`. Returns None for points that never escape and for
+/// formulas without a complex derivative.
+pub fn distance_estimate(config: &RenderConfig, point: C64) -> Option<f64> {
+    estimate_from(config, &config.escape(point))
+}
+
+/* Same as distance_estimate, from a result `escape` already returned */
+fn estimate_from(config: &RenderConfig, result: &EscapeResult) -> Option<f64> {
+    if config.formula != Formula::Mandelbrot || result.in_set {
+        return None;
+    }
+    let r = result.final_z.norm();
+    Some(0.5 * r * r.ln() / result.derivative.norm())
+}
+
+/// Colors each pixel by blending the interior color over the normal exterior
+/// color by the estimated fraction of the pixel covered by the set. Gives
+/// antialiased edges for the price of one orbit per pixel.
+pub fn coverage_colors(config: &RenderConfig, palette: &Palette) -> Vec<MathyColor<f64>> {
+    let interior: MathyColor<f64> = palette.get_mathy_color(NotNan::new(1.0).unwrap());
+    flat_map_rows(0..config.height, config, |h: i32| {
+        (0..config.width).map(move |w: i32| -> MathyColor<f64> {
+            let point = config.pixel_to_complex(w, h);
+            /* Pixel footprint measured locally so curved framings work too */
+            let pixel_size = (config.pixel_to_complex(w + 1, h) - point).norm();
+            let exterior =
+                palette.get_mathy_color(NotNan::try_from(config.escape_position(point)).unwrap());
+            match distance_estimate(config, point) {
+                None => exterior,
+                Some(distance) => {
+                    let coverage = (1.0 - distance / pixel_size).clamp(0.0, 1.0);
+                    exterior.lerp(interior, coverage)
+                }
+            }
+        })
+    })
+}
+
+/// Colors each pixel from its center sample unless that sample escapes within
+/// ADAPTIVE_TRIGGER_PIXELS of the set, in which case it takes `supersample`.
+/// Features smaller than a pixel, which one sample per pixel skips, sit
+/// exactly where the estimate is small. Escaping pixels without an estimate
+/// are always supersampled; pixels inside the set never are.
+pub fn adaptive_colors<F>(
+    config: &RenderConfig,
+    palette: &Palette,
+    supersample: F,
+) -> Vec<MathyColor<f64>>
+where
+    F: Fn(i32, i32) -> MathyColor<f64> + Sync,
+{
+    flat_map_rows(0..config.height, config, |h: i32| {
+        let supersample = &supersample;
+        (0..config.width).map(move |w: i32| -> MathyColor<f64> {
+            let point = config.pixel_to_complex(w, h);
+            let result = config.escape(point);
+            let pixel_size = (config.pixel_to_complex(w + 1, h) - point).norm();
+            let near = !result.in_set
+                && estimate_from(config, &result)
+                    .is_none_or(|d| d < ADAPTIVE_TRIGGER_PIXELS * pixel_size);
+            if near {
+                supersample(w, h)
+            } else {
+                palette.get_mathy_color(NotNan::try_from(config.position_of(&result)).unwrap())
+            }
+        })
+    })
+}
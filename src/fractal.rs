@@ -0,0 +1,150 @@
+use crate::C64;
+
+/// One escape-time iteration formula. `diverges_in` is generic over this, so
+/// a new formula only needs `step`; the other hooks have defaults that fit
+/// Mandelbrot-style sets, where the pixel's point is both z0 and c.
+pub trait Fractal {
+    /// z_{n+1} from z_n and the constant c.
+    fn step(&self, z: C64, c: C64) -> C64;
+
+    /// d(step)/d(point) from z, its derivative `dz` and `dc`. NaN, the
+    /// default, for formulas without a complex derivative.
+    fn derivative(&self, _z: C64, _dz: C64, _dc: C64) -> C64 {
+        C64::new(f64::NAN, f64::NAN)
+    }
+
+    /// The first z and the constant c of the orbit through a pixel's point.
+    fn init(&self, point: C64) -> (C64, C64) {
+        (point, point)
+    }
+
+    /// (dz0, dc): how the z0 and c returned by `init` move with the point.
+    fn derivative_seed(&self) -> (C64, C64) {
+        (C64::new(1.0, 0.0), C64::new(1.0, 0.0))
+    }
+}
+
+/// z^2 + c
+pub struct Mandelbrot;
+
+impl Fractal for Mandelbrot {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        z * z + c
+    }
+
+    fn derivative(&self, z: C64, dz: C64, dc: C64) -> C64 {
+        2.0 * z * dz + dc
+    }
+}
+
+/// (|Re z| + i|Im z|)^2 + c
+pub struct BurningShip;
+
+impl Fractal for BurningShip {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        Mandelbrot.step(C64::new(z.re.abs(), z.im.abs()), c)
+    }
+}
+
+/// |Re(z^2)| + i Im(z^2) + c
+pub struct Celtic;
+
+impl Fractal for Celtic {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        let sq = z * z;
+        C64::new(sq.re.abs(), sq.im) + c
+    }
+}
+
+/// (|Re z| - i Im z)^2 + c
+pub struct Perpendicular;
+
+impl Fractal for Perpendicular {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        Mandelbrot.step(C64::new(z.re.abs(), -z.im), c)
+    }
+}
+
+/// Re(z^2) + 2i |Re z| Im z + c
+pub struct Heart;
+
+impl Fractal for Heart {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        C64::new(z.re * z.re - z.im * z.im, 2.0 * z.re.abs() * z.im) + c
+    }
+}
+
+/// z^d + c for any real d, negative and fractional included
+pub struct Multibrot {
+    pub exponent: f64,
+}
+
+impl Fractal for Multibrot {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        power(z, self.exponent) + c
+    }
+
+    fn derivative(&self, z: C64, dz: C64, dc: C64) -> C64 {
+        self.exponent * power(z, self.exponent - 1.0) * dz + dc
+    }
+}
+
+/// The Julia set of `formula` for the constant `k`: each point is z0 instead.
+pub struct Julia<F> {
+    pub formula: F,
+    pub k: C64,
+}
+
+impl<F: Fractal> Fractal for Julia<F> {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        self.formula.step(z, c)
+    }
+
+    fn derivative(&self, z: C64, dz: C64, dc: C64) -> C64 {
+        self.formula.derivative(z, dz, dc)
+    }
+
+    fn init(&self, point: C64) -> (C64, C64) {
+        (point, self.k)
+    }
+
+    fn derivative_seed(&self) -> (C64, C64) {
+        (C64::new(1.0, 0.0), C64::new(0.0, 0.0))
+    }
+}
+
+/// `formula` with every orbit started at the fixed `z0` instead of at c.
+pub struct StartAt<F> {
+    pub formula: F,
+    pub z0: C64,
+}
+
+impl<F: Fractal> Fractal for StartAt<F> {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        self.formula.step(z, c)
+    }
+
+    fn derivative(&self, z: C64, dz: C64, dc: C64) -> C64 {
+        self.formula.derivative(z, dz, dc)
+    }
+
+    fn init(&self, point: C64) -> (C64, C64) {
+        (self.z0, point)
+    }
+
+    fn derivative_seed(&self) -> (C64, C64) {
+        (C64::new(0.0, 0.0), C64::new(1.0, 0.0))
+    }
+}
+
+fn power(z: C64, exponent: f64) -> C64 {
+    if z == C64::new(0.0, 0.0) && exponent < 0.0 {
+        /* 0^d blows up for negative d; report it as escaped instead of NaN */
+        return C64::new(f64::INFINITY, 0.0);
+    }
+    if exponent.fract() == 0.0 && exponent.abs() <= i32::MAX as f64 {
+        return z.powi(exponent as i32);
+    }
+    /* Principal branch: arg z in (-pi, pi], so the cut runs along the negative real axis */
+    z.powf(exponent)
+}
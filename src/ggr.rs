@@ -0,0 +1,242 @@
+use std::f64::consts::PI;
+
+use image::Rgb;
+use ordered_float::NotNan;
+
+/* Stops per segment when a blend or HSV coloring can't be drawn with straight lines */
+const SEGMENT_SAMPLES: usize = 16;
+/* How far a segment's first stop moves right when it doesn't continue the previous segment */
+const SEAM_OFFSET: f64 = 1e-9;
+
+/// A palette key and its color.
+pub type Stop = (NotNan<f64>, Rgb<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Blend {
+    Linear,
+    Curved,
+    Sine,
+    SphereIncreasing,
+    SphereDecreasing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Coloring {
+    Rgb,
+    HsvCounterClockwise,
+    HsvClockwise,
+}
+
+struct Segment {
+    left: f64,
+    middle: f64,
+    right: f64,
+    left_color: [f64; 3],
+    right_color: [f64; 3],
+    blend: Blend,
+    coloring: Coloring,
+}
+
+impl Segment {
+    fn parse(line: &str, number: usize) -> Result<Segment, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 13 {
+            return Err(format!(
+                "segment {number} has {} fields, needs 13",
+                fields.len()
+            ));
+        }
+        let float = |i: usize| -> Result<f64, String> {
+            fields[i]
+                .parse::<f64>()
+                .map_err(|_| format!("segment {number}: '{}' is not a number", fields[i]))
+        };
+        let int = |i: usize| -> Result<i32, String> {
+            fields[i]
+                .parse::<i32>()
+                .map_err(|_| format!("segment {number}: '{}' is not an integer", fields[i]))
+        };
+        let blend = match int(11)? {
+            0 => Blend::Linear,
+            1 => Blend::Curved,
+            2 => Blend::Sine,
+            3 => Blend::SphereIncreasing,
+            4 => Blend::SphereDecreasing,
+            other => {
+                eprintln!(
+                    "warning: segment {number} has unsupported blend type {other}, using linear"
+                );
+                Blend::Linear
+            }
+        };
+        let coloring = match int(12)? {
+            0 => Coloring::Rgb,
+            1 => Coloring::HsvCounterClockwise,
+            2 => Coloring::HsvClockwise,
+            other => {
+                eprintln!(
+                    "warning: segment {number} has unsupported coloring type {other}, using RGB"
+                );
+                Coloring::Rgb
+            }
+        };
+        /* Newer files name foreground/background endpoints, which a file can't pin down */
+        if fields.len() >= 15 && (int(13)? != 0 || int(14)? != 0) {
+            eprintln!("warning: segment {number} uses context colors, using its stored colors");
+        }
+        Ok(Segment {
+            left: float(0)?,
+            middle: float(1)?,
+            right: float(2)?,
+            left_color: [float(3)?, float(4)?, float(5)?],
+            right_color: [float(7)?, float(8)?, float(9)?],
+            blend,
+            coloring,
+        })
+    }
+
+    /* Blend factor at `p`, a position relative to the segment in [0, 1] */
+    fn factor(&self, p: f64) -> f64 {
+        let width = self.right - self.left;
+        let m: f64 = if width > 0.0 {
+            ((self.middle - self.left) / width).clamp(1e-6, 1.0 - 1e-6)
+        } else {
+            0.5
+        };
+        let linear = |p: f64| -> f64 {
+            if p <= m {
+                0.5 * p / m
+            } else {
+                0.5 + 0.5 * (p - m) / (1.0 - m)
+            }
+        };
+        match self.blend {
+            Blend::Linear => linear(p),
+            Blend::Curved => p.powf(0.5f64.ln() / m.ln()),
+            Blend::Sine => ((-PI / 2.0 + PI * linear(p)).sin() + 1.0) / 2.0,
+            Blend::SphereIncreasing => (1.0 - (linear(p) - 1.0).powi(2)).sqrt(),
+            Blend::SphereDecreasing => 1.0 - (1.0 - linear(p).powi(2)).sqrt(),
+        }
+    }
+
+    fn color(&self, f: f64) -> Rgb<u8> {
+        let [r, g, b] = match self.coloring {
+            Coloring::Rgb => [0, 1, 2]
+                .map(|i| self.left_color[i] + (self.right_color[i] - self.left_color[i]) * f),
+            Coloring::HsvCounterClockwise | Coloring::HsvClockwise => {
+                let [h0, s0, v0] = rgb_to_hsv(self.left_color);
+                let [h1, s1, v1] = rgb_to_hsv(self.right_color);
+                /* Walk the hue circle the way the segment asks, even the long way round */
+                let delta: f64 = if self.coloring == Coloring::HsvCounterClockwise {
+                    (h1 - h0).rem_euclid(1.0)
+                } else {
+                    -(h0 - h1).rem_euclid(1.0)
+                };
+                hsv_to_rgb([
+                    (h0 + delta * f).rem_euclid(1.0),
+                    s0 + (s1 - s0) * f,
+                    v0 + (v1 - v0) * f,
+                ])
+            }
+        };
+        Rgb([r, g, b].map(|c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }
+
+    /* Linear RGB segments are exact with stops at both ends and the midpoint */
+    fn stops(&self) -> Vec<(f64, Rgb<u8>)> {
+        let width = self.right - self.left;
+        if self.blend == Blend::Linear && self.coloring == Coloring::Rgb {
+            return vec![
+                (self.left, self.color(0.0)),
+                (self.middle, self.color(0.5)),
+                (self.right, self.color(1.0)),
+            ];
+        }
+        (0..=SEGMENT_SAMPLES)
+            .map(|i| {
+                let p = i as f64 / SEGMENT_SAMPLES as f64;
+                (self.left + p * width, self.color(self.factor(p)))
+            })
+            .collect()
+    }
+}
+
+fn rgb_to_hsv([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let hue = if chroma == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / chroma).rem_euclid(6.0) / 6.0
+    } else if max == g {
+        ((b - r) / chroma + 2.0) / 6.0
+    } else {
+        ((r - g) / chroma + 4.0) / 6.0
+    };
+    let saturation = if max == 0.0 { 0.0 } else { chroma / max };
+    [hue, saturation, max]
+}
+
+fn hsv_to_rgb([h, s, v]: [f64; 3]) -> [f64; 3] {
+    let sector = h * 6.0;
+    let chroma = v * s;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as i32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = v - chroma;
+    [r + m, g + m, b + m]
+}
+
+/// Converts the text of a GIMP .ggr file into palette stops. Curved, sine and
+/// sphere blends and HSV colorings are sampled into several linear stops;
+/// alpha is dropped.
+pub fn parse_stops(text: &str) -> Result<Vec<Stop>, String> {
+    let mut lines = text.lines().map(str::trim).filter(|l| !l.is_empty());
+    if lines.next() != Some("GIMP Gradient") {
+        return Err("missing 'GIMP Gradient' header".to_string());
+    }
+    let mut count_line = lines.next().ok_or("missing segment count")?;
+    if count_line.starts_with("Name:") {
+        count_line = lines.next().ok_or("missing segment count")?;
+    }
+    let count: usize = count_line
+        .parse()
+        .map_err(|_| format!("'{count_line}' is not a segment count"))?;
+    let segments: Vec<Segment> = lines
+        .take(count)
+        .enumerate()
+        .map(|(i, line)| Segment::parse(line, i + 1))
+        .collect::<Result<_, _>>()?;
+    if segments.len() < count {
+        return Err(format!(
+            "expected {count} segments, found {}",
+            segments.len()
+        ));
+    }
+
+    let mut stops: Vec<Stop> = Vec::new();
+    for segment in &segments {
+        for (i, (key, color)) in segment.stops().into_iter().enumerate() {
+            /* Keep a hard edge where this segment doesn't start in the previous one's end color */
+            let key = match stops.last() {
+                Some((last, last_color)) if i == 0 && last.into_inner() == key => {
+                    if *last_color == color {
+                        continue;
+                    }
+                    key + SEAM_OFFSET
+                }
+                _ => key,
+            };
+            let key = NotNan::new(key).map_err(|_| "segment position is NaN".to_string())?;
+            stops.push((key, color));
+        }
+    }
+    Ok(stops)
+}
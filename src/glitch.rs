@@ -0,0 +1,23 @@
+use crate::{flat_map_rows, RenderConfig};
+
+/* Escaping on the first step is only suspicious once the view is this deep */
+const SHALLOW_ESCAPE_MIN_ZOOM: f64 = 1e10;
+
+/// Flags pixels whose escape count likely reflects lost f64 precision rather
+/// than the fractal: pixels whose coordinate can't be told apart from the
+/// pixel to their right or below, and, at deep zoom, pixels that escaped within
+/// a single iteration. Row-major, one entry per pixel.
+pub fn suspect_pixels(config: &RenderConfig, velocities: &[Vec<i32>]) -> Vec<bool> {
+    let deep = config
+        .viewport
+        .is_some_and(|v| v.zoom >= SHALLOW_ESCAPE_MIN_ZOOM);
+    flat_map_rows(0..config.height, config, |h: i32| {
+        (0..config.width).map(move |w: i32| -> bool {
+            let point = config.pixel_to_complex(w, h);
+            let collapsed = point == config.pixel_to_complex(w + 1, h)
+                || point == config.pixel_to_complex(w, h + 1);
+            let shallow = deep && velocities[h as usize][w as usize] <= 1;
+            collapsed || shallow
+        })
+    })
+}
@@ -0,0 +1,45 @@
+use rayon::prelude::*;
+
+/// Number of pixels at each escape count, 0 through `max_iter`. Each rayon job
+/// tallies its rows into its own table and the tables are summed at the end.
+pub fn tally(velocities: &[Vec<i32>], max_iter: i32) -> Vec<u64> {
+    let bins: usize = max_iter as usize + 1;
+    velocities
+        .par_iter()
+        .fold(
+            || vec![0u64; bins],
+            |mut counts: Vec<u64>, row: &Vec<i32>| {
+                for velocity in row {
+                    counts[(*velocity).clamp(0, max_iter) as usize] += 1;
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0u64; bins],
+            |mut a: Vec<u64>, b: Vec<u64>| {
+                for (x, y) in a.iter_mut().zip(b) {
+                    *x += y;
+                }
+                a
+            },
+        )
+}
+
+/// Palette position for every escape count, such that each position range
+/// covers the same share of escaping pixels. Points that never escape stay at
+/// 1.0 and are left out of the distribution.
+pub fn equalized_positions(velocities: &[Vec<i32>], max_iter: i32) -> Vec<f64> {
+    let counts = tally(velocities, max_iter);
+    let escaped: u64 = counts[..max_iter as usize].iter().sum::<u64>().max(1);
+    let mut running: u64 = 0;
+    let mut positions: Vec<f64> = counts[..max_iter as usize]
+        .iter()
+        .map(|count| {
+            running += count;
+            running as f64 / escaped as f64
+        })
+        .collect();
+    positions.push(1.0);
+    positions
+}
@@ -0,0 +1,48 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use image::{ImageBuffer, Rgb};
+
+/* Most entries an 8-bit PNG palette can hold */
+const MAX_COLORS: usize = 256;
+
+/// The distinct colors of `imgbuf` in order of first appearance, plus each
+/// pixel's index into them. None when there are more than 256 colors.
+pub fn quantize(imgbuf: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<(Vec<Rgb<u8>>, Vec<u8>)> {
+    let mut colors: Vec<Rgb<u8>> = Vec::new();
+    let mut lookup: HashMap<Rgb<u8>, u8> = HashMap::new();
+    let mut indices: Vec<u8> = Vec::with_capacity(imgbuf.len() / 3);
+    for pixel in imgbuf.pixels() {
+        let index = match lookup.get(pixel) {
+            Some(index) => *index,
+            None => {
+                if colors.len() == MAX_COLORS {
+                    return None;
+                }
+                let index = colors.len() as u8;
+                colors.push(*pixel);
+                lookup.insert(*pixel, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some((colors, indices))
+}
+
+/// Writes an 8-bit indexed PNG from `quantize`'s output.
+pub fn write_png<W: Write>(
+    out: W,
+    width: u32,
+    height: u32,
+    colors: &[Rgb<u8>],
+    indices: &[u8],
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(colors.iter().flat_map(|c| c.0).collect::<Vec<u8>>());
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(indices)?;
+    writer.finish()
+}
@@ -0,0 +1,101 @@
+use clap::ValueEnum;
+use image::Rgb;
+
+use crate::{flat_map_rows, Formula, RenderConfig, C64};
+
+/* Orbits returning this close to an earlier point are taken to be periodic */
+const PERIOD_TOLERANCE: f64 = 1e-10;
+/* Orbits near a component's edge settle slowly, so periods get a longer budget than max_iter */
+const PERIOD_ITERATIONS: i32 = 1000;
+/* Colors for periods 1, 2, 3, ..., repeating for longer periods */
+const PERIOD_COLORS: [Rgb<u8>; 8] = [
+    Rgb([38, 70, 83]),
+    Rgb([42, 157, 143]),
+    Rgb([233, 196, 106]),
+    Rgb([244, 162, 97]),
+    Rgb([231, 111, 81]),
+    Rgb([130, 80, 160]),
+    Rgb([70, 120, 200]),
+    Rgb([200, 200, 200]),
+];
+
+/// How points that never escape are colored.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Coloring {
+    /// The palette's interior color
+    Solid,
+    /// A color per period of the attracting cycle the orbit falls into
+    Period,
+}
+
+/// Whether `point` can be shown to belong to the set rather than merely
+/// outlasting max_iter: Mandelbrot points inside the main cardioid or the
+/// period-2 bulb, or any orbit that falls into a cycle within max_iter steps.
+pub fn in_set(config: &RenderConfig, point: C64) -> bool {
+    period(config, point, config.max_iter).is_some()
+}
+
+/// Length of the cycle the orbit of `point` settles into within `max_iter`
+/// steps, or None if it escapes or hasn't settled yet. Mandelbrot points in
+/// the main cardioid and the period-2 bulb are answered without iterating.
+pub fn period(config: &RenderConfig, point: C64, max_iter: i32) -> Option<i32> {
+    /* The cardioid and bulb are where orbits from 0 settle, so a custom z0 iterates */
+    if config.formula == Formula::Mandelbrot && config.julia.is_none() && config.z0.is_none() {
+        let c: C64 = config.plane_point(point);
+        let (x, y) = (c.re, c.im);
+        let q = (x - 0.25) * (x - 0.25) + y * y;
+        if q * (q + (x - 0.25)) <= 0.25 * y * y {
+            return Some(1);
+        }
+        if (x + 1.0) * (x + 1.0) + y * y <= 0.0625 {
+            return Some(2);
+        }
+    }
+    let (mut z, c) = config.orbit_start(point);
+    /* Brent's cycle detection: compare against a checkpoint moved at powers of two.
+    Once the orbit has settled, the first return to the checkpoint is one period */
+    let mut saved: C64 = z;
+    let mut saved_at: i32 = 0;
+    let mut next_checkpoint: i32 = 1;
+    for i in 1..=max_iter {
+        z = config.formula.next(z, c, config.exponent);
+        if z.norm() >= config.threshold {
+            return None;
+        }
+        if (z - saved).norm() < PERIOD_TOLERANCE {
+            return Some(i - saved_at);
+        }
+        if i == next_checkpoint {
+            saved = z;
+            saved_at = i;
+            next_checkpoint *= 2;
+        }
+    }
+    None
+}
+
+/// Period colors for pixels that reached max_iter and whose cycle was found.
+/// Row-major, one entry per pixel; None leaves the pixel as it is.
+pub fn period_colors(config: &RenderConfig, velocities: &[Vec<i32>]) -> Vec<Option<Rgb<u8>>> {
+    flat_map_rows(0..config.height, config, |h: i32| {
+        (0..config.width).map(move |w: i32| -> Option<Rgb<u8>> {
+            if velocities[h as usize][w as usize] < config.max_iter {
+                return None;
+            }
+            let p = period(config, config.pixel_to_complex(w, h), PERIOD_ITERATIONS)?;
+            Some(PERIOD_COLORS[(p - 1) as usize % PERIOD_COLORS.len()])
+        })
+    })
+}
+
+/// Flags pixels that reached max_iter without being shown to lie in the set,
+/// i.e. the ones that might escape under a larger iteration budget.
+/// Row-major, one entry per pixel.
+pub fn undecided_pixels(config: &RenderConfig, velocities: &[Vec<i32>]) -> Vec<bool> {
+    flat_map_rows(0..config.height, config, |h: i32| {
+        (0..config.width).map(move |w: i32| -> bool {
+            velocities[h as usize][w as usize] >= config.max_iter
+                && !in_set(config, config.pixel_to_complex(w, h))
+        })
+    })
+}
@@ -0,0 +1,87 @@
+use image::{ImageBuffer, Rgba};
+use ordered_float::NotNan;
+use rayon::prelude::*;
+
+use crate::{flat_map_rows, Palette, RenderConfig};
+
+/// One fractal rendered over the layers below it.
+pub struct Layer {
+    pub config: RenderConfig,
+    pub palette: Palette,
+    /// How much of the layer shows, from 0 (invisible) to 1 (opaque)
+    pub opacity: f64,
+    /// Leave points that never escape clear so the layers below show through
+    pub transparent_interior: bool,
+}
+
+impl Layer {
+    /// Colors the layer with straight (not premultiplied) alpha.
+    pub fn render(&self) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+        let config = &self.config;
+        let alpha = (self.opacity.clamp(0.0, 1.0) * u8::MAX as f64).round() as u8;
+        let pixels: Vec<u8> = flat_map_rows(0..config.height, config, |h: i32| {
+            (0..config.width).flat_map(move |w: i32| {
+                let result = config.escape(config.pixel_to_complex(w, h));
+                let position = NotNan::try_from(config.position_of(&result)).unwrap();
+                let [r, g, b] = self.palette.get_color(position).0;
+                let a = if self.transparent_interior && result.in_set {
+                    0
+                } else {
+                    alpha
+                };
+                [r, g, b, a]
+            })
+        });
+        ImageBuffer::from_raw(config.width as u32, config.height as u32, pixels).unwrap()
+    }
+}
+
+/// Stacks the layers bottom first with the Porter-Duff over operator. All
+/// layers must be the same size; the result keeps straight alpha, so it is
+/// opaque wherever some layer is.
+pub fn composite(layers: &[Layer]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (width, height) = (
+        layers[0].config.width as u32,
+        layers[0].config.height as u32,
+    );
+    /* Premultiplied in f64 while blending, so each layer only scales what is below it */
+    let mut acc: Vec<[f64; 4]> = vec![[0.0; 4]; (width * height) as usize];
+    for layer in layers {
+        let image = layer.render();
+        let blend = |(below, above): (&mut [f64; 4], &[u8])| {
+            let alpha = above[3] as f64 / 255.0;
+            for ch in 0..3 {
+                below[ch] = above[ch] as f64 / 255.0 * alpha + below[ch] * (1.0 - alpha);
+            }
+            below[3] = alpha + below[3] * (1.0 - alpha);
+        };
+        if layer.config.single_threaded {
+            acc.iter_mut()
+                .zip(image.as_raw().chunks_exact(4))
+                .for_each(blend);
+        } else {
+            acc.par_iter_mut()
+                .zip(image.as_raw().par_chunks_exact(4))
+                .for_each(blend);
+        }
+    }
+    let pixels: Vec<u8> = acc
+        .iter()
+        .flat_map(|&[r, g, b, a]: &[f64; 4]| {
+            let unpremultiply = |c: f64| -> u8 {
+                if a > 0.0 {
+                    (c / a * 255.0).round().clamp(0.0, 255.0) as u8
+                } else {
+                    0
+                }
+            };
+            [
+                unpremultiply(r),
+                unpremultiply(g),
+                unpremultiply(b),
+                (a * 255.0).round() as u8,
+            ]
+        })
+        .collect();
+    ImageBuffer::from_raw(width, height, pixels).unwrap()
+}
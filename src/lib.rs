@@ -0,0 +1,1834 @@
+//! Escape-time renderer for the Mandelbrot set and its relatives. `render`
+//! draws an image from a `RenderConfig` and a `Palette`; the `mandelbrot`
+//! binary is the command-line interface built on top.
+
+use clap::ValueEnum;
+use rayon::prelude::*;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use image::{ImageBuffer, Luma, Rgb};
+use num_complex::{Complex, Complex64, ComplexFloat};
+use num_rational::{Ratio, Rational64};
+use num_traits::ToPrimitive;
+use ordered_float::NotNan;
+use serde::{Deserialize, Serialize};
+
+pub mod area;
+pub mod ascii;
+pub mod bench;
+pub mod bloom;
+pub mod buddhabrot;
+pub mod checkpoint;
+pub mod color;
+pub mod color_expr;
+pub mod compare;
+pub mod contour;
+pub mod cycle;
+pub mod deep;
+pub mod diff;
+pub mod distance;
+pub mod fractal;
+pub mod ggr;
+pub mod glitch;
+pub mod histogram;
+pub mod interior;
+pub mod layers;
+#[cfg(feature = "server")]
+pub mod live;
+pub mod lut;
+pub mod overlay;
+pub mod palette;
+pub mod pixel_format;
+pub mod pixels;
+pub mod rays;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod stats;
+pub mod subsample;
+pub mod sweep;
+pub mod target;
+pub mod tiles;
+
+use color::Transfer;
+pub use color::{BlendSpace, MathyColor};
+use deep::{Deep, Fixed, Real};
+pub use fractal::Fractal;
+use fractal::{Julia, StartAt};
+pub use palette::{Palette, PaletteError};
+use pixels::{Coordinates, Order, PixelCoordinates};
+pub use tiles::{render_tiles, Tile};
+
+pub type C64 = Complex64;
+/// The default `RenderConfig::max_iter`.
+pub const ITERMAX: i32 = 100;
+/* Iterations run past the bailout in smooth mode to flatten residual banding */
+const SMOOTH_EXTRA_ITERATIONS: i32 = 3;
+/// Passing this as the output path writes the image to stdout.
+pub const STDOUT_PATH: &str = "-";
+/* Enough samples that the sine palette's waves look continuous */
+const SINE_STOPS: usize = 64;
+
+/// The recurrence iterated for each point.
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Formula {
+    /// z^2 + c
+    Mandelbrot,
+    /// (|Re z| + i|Im z|)^2 + c
+    BurningShip,
+    /// |Re(z^2)| + i Im(z^2) + c
+    Celtic,
+    /// (|Re z| - i Im z)^2 + c
+    Perpendicular,
+    /// Re(z^2) + 2i |Re z| Im z + c
+    Heart,
+    /// z^d + c with d from --exponent
+    Multibrot,
+}
+
+impl Formula {
+    /* `exponent` is only read by Multibrot */
+    fn next(self, z: C64, c: C64, exponent: f64) -> C64 {
+        BuiltIn {
+            formula: self,
+            exponent,
+        }
+        .step(z, c)
+    }
+
+    fn is_even(self) -> bool {
+        /* next(-z, c) == next(z, c), which makes every Julia set symmetric about 0 */
+        matches!(
+            self,
+            Formula::Mandelbrot | Formula::BurningShip | Formula::Celtic
+        )
+    }
+}
+
+/* A built-in formula as a Fractal, handing each step to its implementor */
+#[derive(Debug, Copy, Clone)]
+struct BuiltIn {
+    formula: Formula,
+    exponent: f64,
+}
+
+impl Fractal for BuiltIn {
+    fn step(&self, z: C64, c: C64) -> C64 {
+        match self.formula {
+            Formula::Mandelbrot => fractal::Mandelbrot.step(z, c),
+            Formula::BurningShip => fractal::BurningShip.step(z, c),
+            Formula::Celtic => fractal::Celtic.step(z, c),
+            Formula::Perpendicular => fractal::Perpendicular.step(z, c),
+            Formula::Heart => fractal::Heart.step(z, c),
+            Formula::Multibrot => fractal::Multibrot {
+                exponent: self.exponent,
+            }
+            .step(z, c),
+        }
+    }
+
+    fn derivative(&self, z: C64, dz: C64, dc: C64) -> C64 {
+        match self.formula {
+            Formula::Mandelbrot => fractal::Mandelbrot.derivative(z, dz, dc),
+            Formula::BurningShip => fractal::BurningShip.derivative(z, dz, dc),
+            Formula::Celtic => fractal::Celtic.derivative(z, dz, dc),
+            Formula::Perpendicular => fractal::Perpendicular.derivative(z, dz, dc),
+            Formula::Heart => fractal::Heart.derivative(z, dz, dc),
+            Formula::Multibrot => fractal::Multibrot {
+                exponent: self.exponent,
+            }
+            .derivative(z, dz, dc),
+        }
+    }
+}
+
+impl BuiltIn {
+    /* `step` in any precision; Multibrot only for whole exponents of at least 1 */
+    fn step_in<R: Real>(&self, z: &Complex<R>, c: &Complex<R>) -> Complex<R> {
+        let z: Complex<R> = match self.formula {
+            Formula::Mandelbrot => square(&z.re, &z.im),
+            Formula::BurningShip => square(&z.re.abs(), &z.im.abs()),
+            Formula::Celtic => {
+                let sq = square(&z.re, &z.im);
+                Complex::new(sq.re.abs(), sq.im)
+            }
+            Formula::Perpendicular => square(&z.re.abs(), &z.im.neg()),
+            Formula::Heart => {
+                let im = z.re.abs().mul(&z.im);
+                Complex::new(square(&z.re, &z.im).re, im.add(&im))
+            }
+            Formula::Multibrot => power(z, self.exponent as u32),
+        };
+        Complex::new(z.re.add(&c.re), z.im.add(&c.im))
+    }
+}
+
+/* A fractal `diverges_in` can iterate in the number type R. Every Fractal runs
+in f64; the built-in formulas run in fixed point too */
+trait Orbit<R> {
+    /* `Fractal::init` for a point in R */
+    fn start(&self, point: &Complex<R>) -> (Complex<R>, Complex<R>);
+    /* `Fractal::step` in R */
+    fn next(&self, z: &Complex<R>, c: &Complex<R>) -> Complex<R>;
+}
+
+impl<F: Fractal + ?Sized> Orbit<f64> for F {
+    fn start(&self, point: &C64) -> (C64, C64) {
+        self.init(*point)
+    }
+
+    fn next(&self, z: &C64, c: &C64) -> C64 {
+        self.step(*z, *c)
+    }
+}
+
+impl Orbit<Fixed> for BuiltIn {
+    fn start(&self, point: &Complex<Fixed>) -> (Complex<Fixed>, Complex<Fixed>) {
+        (point.clone(), point.clone())
+    }
+
+    fn next(&self, z: &Complex<Fixed>, c: &Complex<Fixed>) -> Complex<Fixed> {
+        /* Past f64's range z only reads as infinite, and further steps would
+        just make ever longer numbers to multiply */
+        if z.re.exceeds_f64() || z.im.exceeds_f64() {
+            return z.clone();
+        }
+        self.step_in(z, c)
+    }
+}
+
+impl Orbit<Fixed> for Julia<BuiltIn> {
+    fn start(&self, point: &Complex<Fixed>) -> (Complex<Fixed>, Complex<Fixed>) {
+        (point.clone(), like(point, self.k))
+    }
+
+    fn next(&self, z: &Complex<Fixed>, c: &Complex<Fixed>) -> Complex<Fixed> {
+        self.formula.next(z, c)
+    }
+}
+
+impl Orbit<Fixed> for StartAt<BuiltIn> {
+    fn start(&self, point: &Complex<Fixed>) -> (Complex<Fixed>, Complex<Fixed>) {
+        (like(point, self.z0), point.clone())
+    }
+
+    fn next(&self, z: &Complex<Fixed>, c: &Complex<Fixed>) -> Complex<Fixed> {
+        self.formula.next(z, c)
+    }
+}
+
+/* `z` at the precision of `model` */
+fn like<R: Real>(model: &Complex<R>, z: C64) -> Complex<R> {
+    Complex::new(model.re.like(z.re), model.im.like(z.im))
+}
+
+fn to_c64<R: Real>(z: &Complex<R>) -> C64 {
+    C64::new(z.re.to_f64(), z.im.to_f64())
+}
+
+fn square<R: Real>(re: &R, im: &R) -> Complex<R> {
+    let cross = re.mul(im);
+    Complex::new(re.mul(re).sub(&im.mul(im)), cross.add(&cross))
+}
+
+/* z^n for n of at least 1, by repeated squaring */
+fn power<R: Real>(z: &Complex<R>, n: u32) -> Complex<R> {
+    let mul = |a: &Complex<R>, b: &Complex<R>| {
+        Complex::new(
+            a.re.mul(&b.re).sub(&a.im.mul(&b.im)),
+            a.re.mul(&b.im).add(&a.im.mul(&b.re)),
+        )
+    };
+    let mut result: Complex<R> = z.clone();
+    for bit in (0..31 - n.leading_zeros()).rev() {
+        result = square(&result.re, &result.im);
+        if n & (1 << bit) != 0 {
+            result = mul(&result, z);
+        }
+    }
+    result
+}
+
+/// Curated (name, re, im, zoom) views of the Mandelbrot set.
+pub const LOCATIONS: &[(&str, f64, f64, f64)] = &[
+    ("full", -0.5, 0.0, 1.0),
+    ("seahorse", -0.7453, 0.1127, 20.0),
+    ("elephant", 0.2925, 0.015, 40.0),
+    ("spiral", -0.743643887037151, 0.131825904205330, 5000.0),
+    ("triple-spiral", -0.088, 0.654, 50.0),
+    ("minibrot", -1.7548776662, 0.0, 50.0),
+];
+
+/// Resampling filter for supersampled renders.
+pub const DOWNSCALE_FILTER: image::imageops::FilterType = image::imageops::FilterType::Lanczos3;
+/// Output pixels Lanczos3 reaches past each pixel it writes.
+pub const DOWNSCALE_REACH: i32 = 3;
+
+/// The part of the plane an image shows: its center, magnification and turn.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Viewport {
+    /* zoom 1.0 shows RenderConfig::half_height above and below the center */
+    pub center: C64,
+    pub zoom: f64,
+    /* Degrees counterclockwise; bookmarks saved before it existed are upright */
+    #[serde(default)]
+    pub rotation: f64,
+}
+
+impl Viewport {
+    /// The view of a curated location named in `LOCATIONS`.
+    pub fn from_location(name: &str) -> Option<Viewport> {
+        LOCATIONS
+            .iter()
+            .find(|l| l.0 == name)
+            .map(|&(_, re, im, zoom)| Viewport {
+                center: C64::new(re, im),
+                zoom,
+                rotation: 0.0,
+            })
+    }
+
+    /// Centers the rectangle `min`..`max` and zooms until it just fits an image
+    /// of `aspect` (width / height) whose zoom-1 half height is `half_height`.
+    pub fn fit(min: C64, max: C64, aspect: f64, half_height: f64) -> Viewport {
+        let half_span: C64 = (max - min) / 2.0;
+        let needed: f64 = half_span.im.max(half_span.re / aspect);
+        Viewport {
+            center: (min + max) / 2.0,
+            zoom: half_height / needed,
+            rotation: 0.0,
+        }
+    }
+
+    /// The view of the rectangle from `min` to `max`, fitted as `fit` does.
+    /// Fails unless both corners are finite and the box has width and height.
+    pub fn from_bounds(
+        min: C64,
+        max: C64,
+        aspect: f64,
+        half_height: f64,
+    ) -> Result<Viewport, String> {
+        if !(min.is_finite() && max.is_finite()) {
+            return Err(format!("bounds {min} to {max} must be finite"));
+        }
+        if min.re >= max.re || min.im >= max.im {
+            return Err(format!(
+                "bounds {min} to {max} must have re_min < re_max and im_min < im_max"
+            ));
+        }
+        Ok(Viewport::fit(min, max, aspect, half_height))
+    }
+
+    /// The options that select this view again. f64 Display gives the
+    /// shortest decimal that parses back to the same value, so the view
+    /// survives the round trip exactly, however deep the zoom.
+    pub fn cli_args(&self) -> String {
+        let c = self.center;
+        let view = format!("--center={}{:+}i --zoom={}", c.re, c.im, self.zoom);
+        match self.rotation {
+            0.0 => view,
+            rotation => format!("{view} --rotation={rotation}"),
+        }
+    }
+
+    /* Unit factor that turns plane offsets by the rotation */
+    fn turn(&self) -> C64 {
+        C64::from_polar(1.0, self.rotation.to_radians())
+    }
+
+    fn map(&self, base: C64) -> C64 {
+        self.center + self.offset(base)
+    }
+
+    /* Where `base` lands relative to the center */
+    fn offset(&self, base: C64) -> C64 {
+        /* Image rows grow downwards, so flip to keep +im at the top */
+        self.turn() * base.conj() / self.zoom
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Viewport {
+            center: C64::new(-0.5, 0.0),
+            zoom: 1.0,
+            rotation: 0.0,
+        }
+    }
+}
+
+/// Everything that decides which point each pixel shows and how its escape
+/// count is found and mapped onto the palette.
+#[derive(Debug, Clone)]
+pub struct RenderConfig {
+    pub width: i32,
+    pub height: i32,
+    pub threshold: f64,
+    pub formula: Formula,
+    pub exponent: f64,
+    /* None keeps the original inverted framing from `transform` */
+    pub viewport: Option<Viewport>,
+    /* Some(k) iterates z^2 + k from each pixel instead of the Mandelbrot recurrence */
+    pub julia: Option<C64>,
+    /* Some(z) starts Mandelbrot orbits at z; None starts them at c */
+    pub z0: Option<C64>,
+    /* Work granularity for rayon, see `map_rows` */
+    pub chunk_rows: Option<usize>,
+    pub chunk_pixels: usize,
+    pub smooth: bool,
+    /* Counts up to this share the palette's start color; see `count_position` */
+    pub min_count: i32,
+    /* Exponent `spread` raises normalized counts to; 1.0 keeps them linear */
+    pub bias: f64,
+    /* Base of the logarithmic palette remap in `position_of`, if any */
+    pub log_base: Option<f64>,
+    /* Added to every exterior palette position, wrapping past 1.0; see `offset` */
+    pub color_offset: f64,
+    /* Escape counts run from 0 to this; palettes are spread over that range */
+    pub max_iter: i32,
+    /* Steps a point that hasn't escaped by max_iter keeps going before it counts
+    as inside; at least max_iter */
+    pub interior_max_iter: i32,
+    /* Traversal order for `map_rows`; doesn't change the image */
+    pub order: Order,
+    /* Point generation for `map_rows`; Stepped can move points by an ulp or so */
+    pub coordinates: Coordinates,
+    /* Run `map_rows`, `flat_map_rows` and `map_jobs` without rayon, for targets
+    that can't spawn threads */
+    pub single_threaded: bool,
+    /* Distance from the middle row to the top and bottom edges on the pixel
+    grid, before the viewport or `transform` maps it onto the plane. At zoom 1
+    it is how far above and below the center the image reaches; the width
+    follows from the aspect ratio */
+    pub half_height: Rational64,
+    /* Some once the view is deeper than f64 resolves, see `with_precision`;
+    escapes then run in its fixed point */
+    pub deep: Option<Arc<Deep>>,
+}
+
+impl RenderConfig {
+    /// A `width` x `height` render with the command line's defaults: the
+    /// Mandelbrot set in its original framing, escape radius 2, whole counts.
+    pub fn new(width: i32, height: i32) -> RenderConfig {
+        RenderConfig {
+            width,
+            height,
+            threshold: 2.0,
+            formula: Formula::Mandelbrot,
+            exponent: 2.0,
+            viewport: None,
+            julia: None,
+            z0: None,
+            chunk_rows: None,
+            chunk_pixels: 1,
+            smooth: false,
+            min_count: 0,
+            bias: 1.0,
+            log_base: None,
+            color_offset: 0.0,
+            max_iter: ITERMAX,
+            interior_max_iter: ITERMAX,
+            order: Order::RowMajor,
+            coordinates: Coordinates::Exact,
+            single_threaded: cfg!(target_arch = "wasm32"),
+            half_height: Ratio::new(28, 25),
+            deep: None,
+        }
+    }
+
+    /// The same render centered on `center`, magnified `zoom` times.
+    pub fn with_view(self, center: C64, zoom: f64) -> RenderConfig {
+        RenderConfig {
+            viewport: Some(Viewport {
+                center,
+                zoom,
+                rotation: self.viewport.map_or(0.0, |v| v.rotation),
+            }),
+            ..self
+        }
+    }
+
+    /// The same render turned counterclockwise by `degrees` about the center
+    /// of the view, which is the whole set if `with_view` wasn't used.
+    pub fn with_rotation(self, degrees: f64) -> RenderConfig {
+        RenderConfig {
+            viewport: Some(Viewport {
+                rotation: degrees,
+                ..self.viewport.unwrap_or_default()
+            }),
+            ..self
+        }
+    }
+
+    /// The same render colored by the continuous escape count, which runs a
+    /// few steps past the bailout and takes out the banding of whole counts.
+    /// Works best with a large escape radius, see `with_threshold`.
+    pub fn with_smooth(self) -> RenderConfig {
+        RenderConfig {
+            smooth: true,
+            ..self
+        }
+    }
+
+    /// The same render with escape radius `threshold`.
+    pub fn with_threshold(self, threshold: f64) -> RenderConfig {
+        RenderConfig { threshold, ..self }
+    }
+
+    /// The same render of the Julia set for the constant `k`: each point is
+    /// the orbit's start, and `k` is added at every step.
+    ///
+    /// ```
+    /// use mandelbrot::{RenderConfig, C64};
+    ///
+    /// /* z^2 alone keeps the unit disk and sends everything else away */
+    /// let julia = RenderConfig::new(4, 4).with_julia(C64::new(0.0, 0.0));
+    /// assert!(julia.escape(C64::new(0.0, 0.99)).in_set);
+    /// assert!(!julia.escape(C64::new(0.0, 1.01)).in_set);
+    /// ```
+    pub fn with_julia(self, k: C64) -> RenderConfig {
+        RenderConfig {
+            julia: Some(k),
+            ..self
+        }
+    }
+
+    /// The same render in fixed point if the view is deeper than f64
+    /// resolves and the formula allows it, see `deep::Deep::new`. Points from
+    /// `pixel_to_complex` are then offsets from the exact center, which
+    /// `escape` adds back in full precision and `plane_point` in f64.
+    /// The config should have passed `validate`, since the fixed-point
+    /// precision follows its zoom.
+    pub fn with_precision(self) -> RenderConfig {
+        RenderConfig {
+            /* Only decimal centers can fail to convert */
+            deep: Deep::new(&self, None, None).unwrap().map(Arc::new),
+            ..self
+        }
+    }
+
+    /// Like `with_precision`, with the center parts given as decimal strings
+    /// that keep digits past f64. The config is validated first, and a string
+    /// the fixed point can't hold is an error too.
+    pub fn with_decimal_center(
+        self,
+        center_re: Option<&str>,
+        center_im: Option<&str>,
+    ) -> Result<RenderConfig, String> {
+        self.validate()?;
+        Ok(RenderConfig {
+            deep: Deep::new(&self, center_re, center_im)?.map(Arc::new),
+            ..self
+        })
+    }
+
+    /// Where a point from `pixel_to_complex` lies on the plane, to f64
+    /// precision.
+    pub fn plane_point(&self, point: C64) -> C64 {
+        match &self.deep {
+            Some(deep) => deep.origin() + point,
+            None => point,
+        }
+    }
+
+    fn map_point(&self, x: Ratio<i128>, y: Ratio<i128>) -> C64 {
+        /* Correctly rounded, so the result matches the exact coordinate's nearest f64 */
+        self.map_base(C64::new(x.to_f64().unwrap(), y.to_f64().unwrap()))
+    }
+
+    /* Maps a point of the pixel grid's base plane onto the complex plane */
+    fn map_base(&self, base: C64) -> C64 {
+        match (self.viewport, &self.deep) {
+            (None, _) => transform(base),
+            /* Exactly the offset while the view keeps the deep center */
+            (Some(viewport), Some(deep)) => {
+                viewport.offset(base) + (viewport.center - deep.origin())
+            }
+            (Some(viewport), None) => viewport.map(base),
+        }
+    }
+
+    /* Like pixel_to_complex for fractional pixel coordinates, in f64 throughout */
+    fn subpixel_to_complex(&self, x: f64, y: f64) -> C64 {
+        let (x_scale, y_scale) = plane_scales(self);
+        let x_scale: f64 = x_scale.to_f64().unwrap();
+        let y_scale: f64 = y_scale.to_f64().unwrap();
+        let re = 2.0 * x / self.width as f64 * x_scale - x_scale;
+        let im = 2.0 * y / self.height as f64 * y_scale - y_scale;
+        self.map_base(C64::new(re, im))
+    }
+
+    /// Checks that every pixel gets a finite coordinate: at least 1x1 pixels
+    /// and a positive finite zoom around a finite center. The iteration
+    /// budgets must leave room for at least one escaping count.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.width < 1 || self.height < 1 {
+            return Err(format!(
+                "image size {}x{} must be at least 1x1",
+                self.width, self.height
+            ));
+        }
+        if self.max_iter < 1 {
+            return Err(format!("max-iter {} must be at least 1", self.max_iter));
+        }
+        if self.interior_max_iter < self.max_iter {
+            return Err(format!(
+                "interior-max-iter {} must be at least max-iter {}",
+                self.interior_max_iter, self.max_iter
+            ));
+        }
+        if self.min_count >= self.max_iter {
+            return Err(format!(
+                "min-count {} must be below max-iter {}",
+                self.min_count, self.max_iter
+            ));
+        }
+        if let Some(viewport) = self.viewport {
+            if !(viewport.zoom.is_finite() && viewport.zoom > 0.0) {
+                return Err(format!(
+                    "zoom {} must be positive and finite",
+                    viewport.zoom
+                ));
+            }
+            if !(viewport.center.re.is_finite() && viewport.center.im.is_finite()) {
+                return Err(format!("center {} must be finite", viewport.center));
+            }
+            if !viewport.rotation.is_finite() {
+                return Err(format!("rotation {} must be finite", viewport.rotation));
+            }
+        }
+        let corners = [
+            (0, 0),
+            (self.width - 1, 0),
+            (0, self.height - 1),
+            (self.width - 1, self.height - 1),
+        ];
+        for (w, h) in corners {
+            let c = self.pixel_to_complex(w, h);
+            if !(c.re.is_finite() && c.im.is_finite()) {
+                return Err(format!(
+                    "pixel ({w}, {h}) maps to {c}, outside the f64 range"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /* Area of the complex plane covered by a pixel at (x, y); both mappings are
+    conformal, so that is the base pixel area times |f'|^2 */
+    fn subpixel_area(&self, x: f64, y: f64) -> f64 {
+        let (x_scale, y_scale) = plane_scales(self);
+        let x_scale: f64 = x_scale.to_f64().unwrap();
+        let y_scale: f64 = y_scale.to_f64().unwrap();
+        let base_area = 4.0 * x_scale * y_scale / (self.width as f64 * self.height as f64);
+        let stretch: f64 = match self.viewport {
+            None => {
+                let re = 2.0 * x / self.width as f64 * x_scale - x_scale;
+                let im = 2.0 * y / self.height as f64 * y_scale - y_scale;
+                0.4 / (C64::new(re, im) + C64::new(0.0, -0.53)).norm_sqr()
+            }
+            Some(viewport) => 1.0 / viewport.zoom,
+        };
+        base_area * stretch * stretch
+    }
+
+    /// The point at the top-left corner of pixel (w, h), which supersampling
+    /// offsets into the pixel from.
+    pub fn pixel_to_complex(&self, w: i32, h: i32) -> C64 {
+        let (x_scale, y_scale) = plane_scales(self);
+        let x = base_coordinate(w as i64, self.width as i64, x_scale);
+        let y = base_coordinate(h as i64, self.height as i64, y_scale);
+        self.map_point(x, y)
+    }
+
+    /// Iterates the config's formula from a point until it escapes or the cap,
+    /// in fixed point for deep views.
+    pub fn escape(&self, point: C64) -> EscapeResult {
+        match &self.deep {
+            Some(deep) => self.escape_in(deep.point(point)),
+            None => self.escape_in(point),
+        }
+    }
+
+    fn escape_in<R: Real>(&self, point: Complex<R>) -> EscapeResult
+    where
+        BuiltIn: Orbit<R>,
+        Julia<BuiltIn>: Orbit<R>,
+        StartAt<BuiltIn>: Orbit<R>,
+    {
+        let formula = BuiltIn {
+            formula: self.formula,
+            exponent: self.exponent,
+        };
+        match (self.julia, self.z0) {
+            (Some(k), _) => diverges_in(&Julia { formula, k }, point, self),
+            (None, Some(z0)) => diverges_in(&StartAt { formula, z0 }, point, self),
+            (None, None) => diverges_in(&formula, point, self),
+        }
+    }
+
+    /* First z and the constant c of the orbit through `point`, as the Julia
+    and StartAt fractals that `escape` picks would start it, in f64 */
+    fn orbit_start(&self, point: C64) -> (C64, C64) {
+        let point: C64 = self.plane_point(point);
+        match self.julia {
+            Some(k) => (point, k),
+            None => (self.z0.unwrap_or(point), point),
+        }
+    }
+
+    /// Steps until |Re(z)| and until |Im(z)| first reach the threshold, each
+    /// max_iter if it never does.
+    pub fn axis_escape(&self, point: C64) -> (i32, i32) {
+        let (z0, c) = self.orbit_start(point);
+        axis_escape_counts(
+            z0,
+            c,
+            self.max_iter,
+            self.threshold,
+            self.formula,
+            self.exponent,
+        )
+    }
+
+    /// Palette position in [0, 1] for a point, smooth or whole-count per the config.
+    pub fn escape_position(&self, point: C64) -> f64 {
+        self.position_of(&self.escape(point))
+    }
+
+    /// Same as `escape_position`, from a result `escape` already returned.
+    pub fn position_of(&self, result: &EscapeResult) -> f64 {
+        let count: f64 = if self.smooth {
+            result.smooth
+        } else {
+            result.count as f64
+        };
+        match self.log_base {
+            /* Stays in [0, 1] since log_B(1) = 0 and log_B(B) = 1 */
+            Some(base) => {
+                let t: f64 = self.spread(count);
+                self.offset((1.0 + (base - 1.0) * t.max(0.0)).log(base).min(1.0))
+            }
+            None => self.count_position(count),
+        }
+    }
+
+    /// Palette position of an escape count, as `position_of` gives it without
+    /// a log base.
+    pub fn count_position(&self, count: f64) -> f64 {
+        self.offset(self.spread(count))
+    }
+
+    /* Spreads the palette over min_count..max_iter, with lower counts at 0,
+    bent by bias; 0 and 1 stay put, so the interior keeps its color */
+    fn spread(&self, count: f64) -> f64 {
+        let min = self.min_count as f64;
+        ((count - min) / (self.max_iter as f64 - min))
+            .max(0.0)
+            .powf(self.bias)
+    }
+
+    /* Shifts an exterior position by color_offset, wrapping into [0, 1) the way
+    palette cycling does; 1.0 is the interior and keeps its color */
+    fn offset(&self, position: f64) -> f64 {
+        if self.color_offset == 0.0 || position >= 1.0 {
+            position
+        } else {
+            (position + self.color_offset).rem_euclid(1.0)
+        }
+    }
+
+    /// Steps the escape loop actually ran for a point, including the smoothing
+    /// extras and any past max_iter that interior_max_iter allows.
+    pub fn iterations_performed(&self, point: C64) -> i32 {
+        self.escape(point).steps
+    }
+
+    fn is_point_symmetric(&self) -> bool {
+        let centered = matches!(self.viewport, Some(v) if v.center == C64::new(0.0, 0.0));
+        self.julia.is_some() && self.formula.is_even() && centered
+    }
+
+    /* Inverse of map_point in fractional pixel units; may lie off the image */
+    fn complex_to_plane(&self, c: C64) -> Option<(f64, f64)> {
+        let base: C64 = match self.viewport {
+            None if c == C64::new(0.0, 0.0) => return None,
+            None => -C64::i() * (0.4 / c) + C64::new(0.0, 0.53),
+            Some(viewport) => {
+                ((c - viewport.center) * viewport.zoom * viewport.turn().conj()).conj()
+            }
+        };
+        let (x_scale, y_scale) = plane_scales(self);
+        let x_scale: f64 = x_scale.to_f64().unwrap();
+        let y_scale: f64 = y_scale.to_f64().unwrap();
+        let x = (base.re + x_scale) / (2.0 * x_scale) * self.width as f64;
+        let y = (base.im + y_scale) / (2.0 * y_scale) * self.height as f64;
+        Some((x, y))
+    }
+
+    fn complex_to_pixel(&self, c: C64) -> Option<(i32, i32)> {
+        /* Truncated to the containing pixel */
+        let (x, y) = self.complex_to_plane(c)?;
+        let (x, y) = (x.floor(), y.floor());
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return None;
+        }
+        Some((x as i32, y as i32))
+    }
+}
+
+/// Parses a complex number such as `-0.8+0.156i`, `0.3` or `i`.
+pub fn parse_complex(s: &str) -> Result<C64, String> {
+    /* Accepts "a", "bi", "a+bi" and "a-bi" */
+    let s: String = s.chars().filter(|ch| !ch.is_whitespace()).collect();
+    let parse = |part: &str| -> Result<f64, String> {
+        match part {
+            "" | "+" => Ok(1.0),
+            "-" => Ok(-1.0),
+            _ => part
+                .parse::<f64>()
+                .map_err(|e| format!("invalid number '{part}': {e}")),
+        }
+    };
+    let Some(body) = s.strip_suffix('i') else {
+        return s
+            .parse::<f64>()
+            .map(|re| C64::new(re, 0.0))
+            .map_err(|e| format!("invalid number '{s}': {e}"));
+    };
+    let split = body
+        .char_indices()
+        .skip(1)
+        .filter(|&(i, ch)| {
+            (ch == '+' || ch == '-') && !matches!(body.as_bytes()[i - 1], b'e' | b'E')
+        })
+        .last()
+        .map(|(i, _)| i);
+    match split {
+        Some(i) => Ok(C64::new(parse(&body[..i])?, parse(&body[i..])?)),
+        None => Ok(C64::new(0.0, parse(body)?)),
+    }
+}
+
+/// The built-in palettes.
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaletteName {
+    /// Pale rose fading to near-black, the original colors
+    Default,
+    /// Three sine waves out of phase, fading to black
+    Sine,
+}
+
+/// The palette the command line uses unless told otherwise.
+pub fn default_palette() -> Palette {
+    build_palette(PaletteName::Default, false).0
+}
+
+/// Renders the whole image, coloring each pixel by its escape count, or by
+/// the continuous count for a config `with_smooth`.
+pub fn render(config: &RenderConfig, palette: &Palette) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    if config.smooth {
+        let positions: Vec<Vec<f64>> =
+            map_rows(0..config.height, config, |c: C64| config.escape_position(c));
+        return ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+            palette.get_color(NotNan::try_from(positions[y as usize][x as usize]).unwrap())
+        });
+    }
+    let velocities = get_divergence_vel(config);
+    let count_lut = lut::ColorLut::counts(palette, config);
+    ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+        count_lut.count(velocities[y as usize][x as usize]).unwrap()
+    })
+}
+
+/// Renders like `render`, iterating `fractal` instead of the config's formula,
+/// Julia constant and z0. The rest of `config` still frames the view, bounds
+/// the iterations and places the counts on the palette. `fractal` iterates in
+/// f64 even where `with_precision` chose fixed point for the built-in formulas.
+///
+/// ```
+/// use mandelbrot::{default_palette, render_with, Fractal, RenderConfig, C64};
+///
+/// /// conj(z)^2 + c
+/// struct Tricorn;
+///
+/// impl Fractal for Tricorn {
+///     fn step(&self, z: C64, c: C64) -> C64 {
+///         z.conj() * z.conj() + c
+///     }
+/// }
+///
+/// let image = render_with(&RenderConfig::new(64, 36), &Tricorn, &default_palette());
+/// assert_eq!(image.dimensions(), (64, 36));
+/// ```
+pub fn render_with<F: Fractal + Sync + ?Sized>(
+    config: &RenderConfig,
+    fractal: &F,
+    palette: &Palette,
+) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let results: Vec<Vec<EscapeResult>> = map_rows(0..config.height, config, |c: C64| {
+        diverges_in(fractal, config.plane_point(c), config)
+    });
+    let count_lut = lut::ColorLut::counts(palette, config);
+    ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+        let result = &results[y as usize][x as usize];
+        if config.smooth {
+            palette.get_color(NotNan::try_from(config.position_of(result)).unwrap())
+        } else {
+            count_lut.count(result.count).unwrap()
+        }
+    })
+}
+
+/// Sets every pixel to `color` of its row-major index, in parallel unless the
+/// config is single-threaded.
+pub fn fill_pixels<F>(imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, config: &RenderConfig, color: F)
+where
+    F: Fn(usize) -> Rgb<u8> + Sync,
+{
+    let set = |(i, pixel): (usize, &mut [u8])| pixel.copy_from_slice(&color(i).0);
+    if config.single_threaded {
+        imgbuf.chunks_exact_mut(3).enumerate().for_each(set);
+    } else {
+        imgbuf
+            .par_chunks_exact_mut(3)
+            .with_min_len(config.chunk_pixels)
+            .enumerate()
+            .for_each(set);
+    }
+}
+
+/// Returns the named palette and the interior color that goes with it.
+/// A `cycling` palette fades back to its start so it can be rotated.
+pub fn build_palette(name: PaletteName, cycling: bool) -> (Palette, Rgb<u8>) {
+    let cols: Vec<Rgb<u8>> = vec![
+        Rgb([229, 208, 204]),
+        Rgb([229, 208, 204]),
+        Rgb([23, 33, 33]),
+    ];
+
+    let col_keys: Vec<NotNan<f64>> = vec![
+        NotNan::try_from(0.0).unwrap(),
+        NotNan::try_from(0.15).unwrap(),
+        NotNan::try_from(1.0).unwrap(),
+    ];
+
+    let interior: Rgb<u8> = *cols.last().unwrap();
+    let mut palette: Palette = match name {
+        PaletteName::Sine => Palette::from_fn(SINE_STOPS, palette::sine),
+        PaletteName::Default => {
+            let mut palette: Palette = Palette::new();
+            for (k, v) in std::iter::zip(col_keys, cols) {
+                /* A cycling palette fades back to its start over the second half */
+                let key: NotNan<f64> = if cycling { k / 2.0 } else { k };
+                palette.add_col(key, &v);
+            }
+            palette
+        }
+    };
+    if cycling {
+        palette.make_cyclic().unwrap();
+    }
+    palette.validate(false).unwrap();
+    (palette, interior)
+}
+
+/// Set membership at `width` x `height` with the rest of `config`: 255 where a
+/// pixel reaches max_iter, 0 where it escapes. No palette work is done, so this
+/// is cheaper than a render and can serve as an alpha channel.
+///
+/// ```
+/// use mandelbrot::{render_mask, RenderConfig, C64};
+///
+/// let inside = RenderConfig::new(4, 4).with_view(C64::new(-0.1, 0.0), 10.0);
+/// assert!(render_mask(4, 4, &inside).pixels().all(|p| p.0 == [255]));
+/// let outside = RenderConfig::new(4, 4).with_view(C64::new(3.0, 0.0), 10.0);
+/// assert!(render_mask(4, 4, &outside).pixels().all(|p| p.0 == [0]));
+/// ```
+pub fn render_mask(
+    width: i32,
+    height: i32,
+    config: &RenderConfig,
+) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+    let config = RenderConfig {
+        width,
+        height,
+        ..config.clone()
+    };
+    let levels: Vec<u8> = map_rows(0..height, &config, |c: C64| {
+        if config.escape(c).in_set {
+            u8::MAX
+        } else {
+            0
+        }
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    ImageBuffer::from_raw(width as u32, height as u32, levels).unwrap()
+}
+
+fn transform(base: C64) -> C64 {
+    let base = (base + C64::new(0.0, -0.53)) * C64::i();
+    if base.abs() == 0.0 {
+        C64::new(10.0, 10.0)
+    } else {
+        0.4 / base
+    }
+}
+
+/// Escape counts of every pixel, one row per image row.
+pub fn get_divergence_vel(config: &RenderConfig) -> Vec<Vec<i32>> {
+    divergence_vel(config, false)
+        .into_iter()
+        .map(|(row, _)| row)
+        .collect()
+}
+
+/// Like `get_divergence_vel`, with the wall-clock time from the start to the
+/// end of each row within that same pass. Other rows share the threads, so
+/// their work counts too; mirrored rows of symmetric views take only the time
+/// of their own pixels. Orders other than row-major don't compute rows as
+/// such and report zero.
+pub fn get_divergence_vel_timed(config: &RenderConfig) -> Vec<(Vec<i32>, Duration)> {
+    divergence_vel(config, true)
+}
+
+/* Escape counts per row, each with its time if `timed`, else zero. Untimed
+passes never read the clock, which wasm32 doesn't have */
+fn divergence_vel(config: &RenderConfig, timed: bool) -> Vec<(Vec<i32>, Duration)> {
+    let (width, height) = (config.width, config.height);
+    let escape_count = |c: C64| config.escape(c).count;
+    if !config.is_point_symmetric() {
+        return map_rows_with(0..height, config, escape_count, timed);
+    }
+
+    /* Pixel (w, h) sits at minus pixel (width - w, height - h), so only the top
+    half is iterated. Column 0 has no mirror inside the image and is computed. */
+    let half: i32 = height / 2 + 1;
+    let mut velocities: Vec<(Vec<i32>, Duration)> =
+        map_rows_with(0..half.min(height), config, escape_count, timed);
+    let mirror_row = |h: i32| -> (Vec<i32>, Duration) {
+        let start: Option<Instant> = timed.then(Instant::now);
+        let source: &Vec<i32> = &velocities[(height - h) as usize].0;
+        let row: Vec<i32> = (0..width)
+            .map(|w: i32| -> i32 {
+                if w == 0 {
+                    config.escape(config.pixel_to_complex(w, h)).count
+                } else {
+                    source[(width - w) as usize]
+                }
+            })
+            .collect();
+        (row, elapsed(start))
+    };
+    let mirrored: Vec<(Vec<i32>, Duration)> = if config.single_threaded {
+        (half..height).map(mirror_row).collect()
+    } else {
+        (half..height).into_par_iter().map(mirror_row).collect()
+    };
+    velocities.extend(mirrored);
+    velocities
+}
+
+/// Escape counts that take `prior`'s value wherever `mask` is false and are
+/// recomputed from `config` wherever it is true, so only flagged pixels cost
+/// any iterations. Both grids must have the image's size; a recomputed count
+/// replaces the prior one outright, even when it is lower.
+pub fn get_divergence_vel_masked(
+    config: &RenderConfig,
+    prior: &[Vec<i32>],
+    mask: &[Vec<bool>],
+) -> Vec<Vec<i32>> {
+    let merge_row = |h: usize| -> Vec<i32> {
+        (0..config.width as usize)
+            .map(|w: usize| {
+                if mask[h][w] {
+                    config
+                        .escape(config.pixel_to_complex(w as i32, h as i32))
+                        .count
+                } else {
+                    prior[h][w]
+                }
+            })
+            .collect()
+    };
+    if config.single_threaded {
+        (0..config.height as usize).map(merge_row).collect()
+    } else {
+        (0..config.height as usize)
+            .into_par_iter()
+            .with_max_len(config.chunk_rows.unwrap_or(usize::MAX))
+            .map(merge_row)
+            .collect()
+    }
+}
+
+/// Exact base-plane coordinate of pixel edge `index` out of `extent`, i.e.
+/// (2 * index / extent - 1) * scale. With `index` and `extent` below 2^62, as
+/// any i32 size times an i32 subsample count is, both parts stay under 2^126,
+/// so i128 can't overflow where i64 would for large images and long scales.
+fn base_coordinate(index: i64, extent: i64, scale: Rational64) -> Ratio<i128> {
+    Ratio::new(
+        (2 * index as i128 - extent as i128) * *scale.numer() as i128,
+        extent as i128 * *scale.denom() as i128,
+    )
+}
+
+fn plane_scales(config: &RenderConfig) -> (Rational64, Rational64) {
+    let aspect_ratio: Rational64 = Rational64::new(config.width as i64, config.height as i64);
+    let y_scale: Rational64 = config.half_height;
+    let x_scale: Rational64 = y_scale * aspect_ratio;
+    (x_scale, y_scale)
+}
+
+/// Escape counts of the image rows in `rows`.
+pub fn get_divergence_rows(rows: Range<i32>, config: &RenderConfig) -> Vec<Vec<i32>> {
+    map_rows(rows, config, |c: C64| config.escape(c).count)
+}
+
+/// `row` of each image row in `rows`, flattened in order. Rows run in
+/// parallel, split at most `chunk_rows` at a time, unless the config is
+/// single-threaded.
+pub fn flat_map_rows<T, I, F>(rows: Range<i32>, config: &RenderConfig, row: F) -> Vec<T>
+where
+    T: Send,
+    I: IntoIterator<Item = T>,
+    F: Fn(i32) -> I + Sync,
+{
+    if config.single_threaded {
+        rows.flat_map(row).collect()
+    } else {
+        rows.into_par_iter()
+            .with_max_len(config.chunk_rows.unwrap_or(usize::MAX))
+            .flat_map_iter(&row)
+            .collect()
+    }
+}
+
+/// `job` of each of `jobs`, in order, in parallel unless the config is
+/// single-threaded.
+pub fn map_jobs<J, T, F>(jobs: Vec<J>, config: &RenderConfig, job: F) -> Vec<T>
+where
+    J: Send,
+    T: Send,
+    F: Fn(J) -> T + Sync,
+{
+    if config.single_threaded {
+        jobs.into_iter().map(job).collect()
+    } else {
+        jobs.into_par_iter().map(&job).collect()
+    }
+}
+
+pub fn map_rows<T, F>(rows: Range<i32>, config: &RenderConfig, f: F) -> Vec<Vec<T>>
+where
+    T: Send,
+    F: Fn(C64) -> T + Sync,
+{
+    map_rows_with(rows, config, f, false)
+        .into_iter()
+        .map(|(row, _)| row)
+        .collect()
+}
+
+/* `map_rows`, each row with its time if `timed` */
+fn map_rows_with<T, F>(
+    rows: Range<i32>,
+    config: &RenderConfig,
+    f: F,
+    timed: bool,
+) -> Vec<(Vec<T>, Duration)>
+where
+    T: Send,
+    F: Fn(C64) -> T + Sync,
+{
+    if config.order != Order::RowMajor {
+        return map_rows_scattered(rows, config, f)
+            .into_iter()
+            .map(|row| (row, Duration::ZERO))
+            .collect();
+    }
+    if config.single_threaded {
+        return rows
+            .map(|h: i32| -> (Vec<T>, Duration) {
+                let start: Option<Instant> = timed.then(Instant::now);
+                let row: Vec<T> = PixelCoordinates::new(config, h..h + 1, Order::RowMajor)
+                    .map(|(_, _, c): (i32, i32, C64)| -> T { f(c) })
+                    .collect();
+                (row, elapsed(start))
+            })
+            .collect();
+    }
+
+    /* Rows near the set cost far more than rows outside it, so bounding the
+    split size keeps late stragglers from idling the other threads */
+    rows.into_par_iter()
+        .with_max_len(config.chunk_rows.unwrap_or(usize::MAX))
+        .map(|h: i32| -> (Vec<T>, Duration) {
+            let start: Option<Instant> = timed.then(Instant::now);
+            let points: Vec<(i32, i32, C64)> =
+                PixelCoordinates::new(config, h..h + 1, Order::RowMajor).collect();
+            let row: Vec<T> = points
+                .into_par_iter()
+                .with_min_len(config.chunk_pixels)
+                .map(|(_, _, c): (i32, i32, C64)| -> T { f(c) })
+                .collect();
+            (row, elapsed(start))
+        })
+        .collect()
+}
+
+/* Time since `start`, or zero if there is none */
+fn elapsed(start: Option<Instant>) -> Duration {
+    start.map_or(Duration::ZERO, |start| start.elapsed())
+}
+
+/* Computes pixels in `config.order` and puts each result back in its row */
+fn map_rows_scattered<T, F>(rows: Range<i32>, config: &RenderConfig, f: F) -> Vec<Vec<T>>
+where
+    T: Send,
+    F: Fn(C64) -> T + Sync,
+{
+    let points: Vec<(i32, i32, C64)> =
+        PixelCoordinates::new(config, rows.clone(), config.order).collect();
+    let compute = |(x, y, c): (i32, i32, C64)| (x, y, f(c));
+    let values: Vec<(i32, i32, T)> = if config.single_threaded {
+        points.into_iter().map(compute).collect()
+    } else {
+        points
+            .into_par_iter()
+            .with_min_len(config.chunk_pixels)
+            .map(compute)
+            .collect()
+    };
+    let mut grid: Vec<Vec<Option<T>>> = rows
+        .clone()
+        .map(|_| (0..config.width).map(|_| None).collect())
+        .collect();
+    for (x, y, value) in values {
+        grid[(y - rows.start) as usize][x as usize] = Some(value);
+    }
+    grid.into_iter()
+        .map(|row| row.into_iter().map(Option::unwrap).collect())
+        .collect()
+}
+
+pub fn get_supersampled_colors(
+    config: &RenderConfig,
+    samples: i32,
+    pattern: subsample::Pattern,
+    filter: subsample::Filter,
+    palette: &Palette,
+    transfer: Option<Transfer>,
+) -> Vec<MathyColor<f64>> {
+    let pixel = supersampler(config, samples, pattern, filter, palette, transfer);
+    flat_map_rows(0..config.height, config, |h: i32| {
+        (0..config.width)
+            .map(|w: i32| pixel(w, h))
+            .collect::<Vec<_>>()
+    })
+}
+
+/// The supersampled color of pixel (w, h), as a closure so callers can pick
+/// which pixels get it.
+pub fn supersampler<'a>(
+    config: &'a RenderConfig,
+    samples: i32,
+    pattern: subsample::Pattern,
+    filter: subsample::Filter,
+    palette: &'a Palette,
+    transfer: Option<Transfer>,
+) -> impl Fn(i32, i32) -> MathyColor<f64> + Sync + 'a {
+    /* On the grid, subsample (i, j) sits at (w + i/samples, h + j/samples), so
+    sample 0 is the plain pixel; other patterns go through f64 offsets */
+    let (x_scale, y_scale) = plane_scales(config);
+    let sub_width: i64 = config.width as i64 * samples as i64;
+    let sub_height: i64 = config.height as i64 * samples as i64;
+
+    move |w: i32, h: i32| -> MathyColor<f64> {
+        let offsets = subsample::offsets(pattern, samples, w, h);
+        let points: Vec<C64> = if pattern == subsample::Pattern::Grid {
+            (0..samples as i64)
+                .flat_map(|j: i64| {
+                    let sub_h = h as i64 * samples as i64 + j;
+                    let y = base_coordinate(sub_h, sub_height, y_scale);
+                    (0..samples as i64).map(move |i: i64| {
+                        let sub_w = w as i64 * samples as i64 + i;
+                        let x = base_coordinate(sub_w, sub_width, x_scale);
+                        config.map_point(x, y)
+                    })
+                })
+                .collect()
+        } else {
+            offsets
+                .iter()
+                .map(|(dx, dy)| config.subpixel_to_complex(w as f64 + dx, h as f64 + dy))
+                .collect()
+        };
+        /* Filters are centered on the mean subsample, which for the grid
+        is half a subsample short of the pixel's middle */
+        let count = offsets.len() as f64;
+        let cx: f64 = offsets.iter().map(|o| o.0).sum::<f64>() / count;
+        let cy: f64 = offsets.iter().map(|o| o.1).sum::<f64>() / count;
+        let mut acc: MathyColor<f64> = MathyColor::new(0.0, 0.0, 0.0);
+        let mut total: f64 = 0.0;
+        for (c, (dx, dy)) in points.into_iter().zip(offsets) {
+            let position = NotNan::try_from(config.escape_position(c)).unwrap();
+            let color = palette.get_mathy_color(position);
+            let weight: f64 = filter.weight(dx - cx, dy - cy);
+            let color = match transfer {
+                Some(t) => t.decode(color),
+                None => color,
+            };
+            acc = acc + color * weight;
+            total += weight;
+        }
+        let avg = acc * (1.0 / total);
+        match transfer {
+            Some(t) => t.encode(avg),
+            None => avg,
+        }
+    }
+}
+
+/// Everything one escape-time iteration learns about a point, so coloring
+/// modes can read what they need instead of iterating again. Escaping means
+/// |z| >= threshold, tested as |z|^2 >= threshold^2.
+#[derive(Debug, Copy, Clone)]
+pub struct EscapeResult {
+    /* Steps to escape, max_iter for points that never do. The orbit starts at
+    the fractal's z0, which for Mandelbrot is c itself, so counts run one lower
+    than the z0 = 0 convention: c = 2 escapes after 0 steps, not 1 */
+    pub count: i32,
+    /* Steps the loop actually ran, smoothing extras included. Unlike `count`
+    it isn't folded back to max_iter, so it shows the work interior_max_iter
+    adds */
+    pub steps: i32,
+    /* Last z computed, including any smoothing steps past the escape */
+    pub final_z: C64,
+    /* dz/d(point) along the same steps; NaN for formulas that aren't holomorphic */
+    pub derivative: C64,
+    /* Normalized iteration count, or the whole count for points in the set */
+    pub smooth: f64,
+    pub in_set: bool,
+}
+
+/* Iterates `fractal` from `point` with the threshold and budgets of `config`;
+its formula settings are left to `fractal`. Points still inside at max_iter get
+up to `interior_max_iter` steps to escape before they count as in the set */
+fn diverges_in<R: Real, F: Fractal + Orbit<R> + ?Sized>(
+    fractal: &F,
+    point: Complex<R>,
+    config: &RenderConfig,
+) -> EscapeResult {
+    let threshold = config.threshold;
+    let (z0, c) = fractal.start(&point);
+    let extra_iterations: i32 = if config.smooth {
+        SMOOTH_EXTRA_ITERATIONS
+    } else {
+        0
+    };
+    let mut count: i32 = 0;
+    let mut accumulator: Complex<R> = z0;
+    /* The bailout, the derivative and anything else that only needs f64 see
+    the accumulator in f64 */
+    let mut z: C64 = to_c64(&accumulator);
+    let (mut derivative, dc): (C64, C64) = fractal.derivative_seed();
+    let mut d1: C64 = C64::new(0.0, 0.0);
+    let mut d2: C64;
+    /* |z| < R exactly when |z|^2 < R^2, and the square skips a sqrt per step */
+    let threshold_sqr: f64 = threshold * threshold;
+
+    while z.norm_sqr() < threshold_sqr && count < config.interior_max_iter {
+        let next_accumulator = fractal.next(&accumulator, &c);
+        let next_z: C64 = to_c64(&next_accumulator);
+        let d = next_z - z;
+        d2 = d1;
+        d1 = d;
+        let second_d = d2 - d1;
+        if second_d.abs() < 0.05 {
+            //return ITERMAX
+        }
+        derivative = fractal.derivative(z, derivative, dc);
+        accumulator = next_accumulator;
+        z = next_z;
+        count += 1;
+    }
+
+    let in_set = count >= config.interior_max_iter;
+    let mut steps: i32 = count;
+    /* The extra steps move z further out without changing the reported count */
+    if !in_set {
+        steps += extra_iterations;
+        for _ in 0..extra_iterations {
+            derivative = fractal.derivative(z, derivative, dc);
+            accumulator = fractal.next(&accumulator, &c);
+            z = to_c64(&accumulator);
+        }
+    }
+
+    /* Counts stay within the palette: inside is max_iter, and late escapes get
+    the last step before it */
+    let max_iter: i32 = config.max_iter;
+    let (count, smooth): (i32, f64) = if in_set {
+        (max_iter, max_iter as f64)
+    } else if count >= max_iter {
+        (max_iter - 1, (max_iter - 1) as f64)
+    } else {
+        let smooth = smooth_count(count + extra_iterations, z, threshold, max_iter);
+        (count, smooth)
+    };
+    EscapeResult {
+        count,
+        steps,
+        final_z: z,
+        derivative,
+        smooth,
+        in_set,
+    }
+}
+
+/* The diverges_in loop, but watching each coordinate on its own. It runs until
+both have passed the threshold, since one part can stay small long after |z|
+has escaped, and gives up at max_iter or once z overflows */
+fn axis_escape_counts(
+    z0: C64,
+    c: C64,
+    max_iter: i32,
+    threshold: f64,
+    formula: Formula,
+    exponent: f64,
+) -> (i32, i32) {
+    let mut z: C64 = z0;
+    let (mut re_count, mut im_count): (Option<i32>, Option<i32>) = (None, None);
+    for count in 0..max_iter {
+        if re_count.is_none() && z.re.abs() >= threshold {
+            re_count = Some(count);
+        }
+        if im_count.is_none() && z.im.abs() >= threshold {
+            im_count = Some(count);
+        }
+        if (re_count.is_some() && im_count.is_some()) || !z.is_finite() {
+            break;
+        }
+        z = formula.next(z, c, exponent);
+    }
+    (re_count.unwrap_or(max_iter), im_count.unwrap_or(max_iter))
+}
+
+/// The orbit z0 = c, z1, ... of the Mandelbrot recurrence, the `accumulator`
+/// values the escape loop steps through, up to and including the first point
+/// with |z| >= threshold. An orbit that never escapes ends after `max_iter`
+/// steps, with `max_iter + 1` points.
+pub fn orbit(c: C64, max_iter: i32, threshold: f64) -> Vec<C64> {
+    orbit_from(c, c, max_iter, threshold, Formula::Mandelbrot, 2.0)
+}
+
+/* The orbit z0, z1, ... of c under `formula` up to and including the first
+point with |z| >= threshold, or through z_{max_iter} if it never escapes */
+fn orbit_from(
+    z0: C64,
+    c: C64,
+    max_iter: i32,
+    threshold: f64,
+    formula: Formula,
+    exponent: f64,
+) -> Vec<C64> {
+    let mut points: Vec<C64> = vec![z0];
+    let mut z: C64 = z0;
+    while z.norm() < threshold && (points.len() as i32) <= max_iter {
+        z = formula.next(z, c, exponent);
+        points.push(z);
+    }
+    points
+}
+
+fn smooth_count(iterations: i32, z: C64, threshold: f64, max_iter: i32) -> f64 {
+    /* Normalized iteration count; `iterations` is how many steps produced `z`.
+    Measuring |z| against the bailout radius keeps nu continuous across the
+    escape boundary for any threshold. */
+    let nu: f64 =
+        iterations as f64 + 1.0 - (z.norm().ln() / threshold.ln()).ln() / std::f64::consts::LN_2;
+    if !nu.is_finite() {
+        /* Overflowed orbits fall back to the whole count */
+        return (iterations as f64).min(max_iter as f64);
+    }
+    nu.clamp(0.0, max_iter as f64)
+}
+
+/// The palette color of an escape value, whole or smoothed.
+pub fn gradient(escape: f64, config: &RenderConfig, palette: &Palette) -> Rgb<u8> {
+    let norm: NotNan<f64> = NotNan::try_from(config.count_position(escape)).unwrap();
+    palette.get_color(norm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_counts_start_from_c() {
+        let config = RenderConfig::new(4, 4);
+        let origin = config.escape(C64::new(0.0, 0.0));
+        assert!(origin.in_set);
+        assert_eq!(origin.count, config.max_iter);
+        let two = config.escape(C64::new(2.0, 0.0));
+        assert!(!two.in_set);
+        assert_eq!(two.count, 0);
+        /* 1 -> 2, out after its first step */
+        assert_eq!(config.escape(C64::new(1.0, 0.0)).count, 1);
+    }
+
+    #[test]
+    fn smoothing_is_continuous_at_any_threshold() {
+        let small = RenderConfig::new(4, 4).with_smooth();
+        let large = small.clone().with_threshold(1000.0);
+        /* Real c past the cusp at 0.25 all escape, a few whole counts apart */
+        let points: Vec<C64> = (0..=2000)
+            .map(|i| C64::new(0.3 + 1.2 * i as f64 / 2000.0, 0.0))
+            .collect();
+        for config in [&small, &large] {
+            let escapes: Vec<EscapeResult> = points.iter().map(|c| config.escape(*c)).collect();
+            assert!(escapes.windows(2).any(|w| w[0].count != w[1].count));
+            for w in escapes.windows(2) {
+                assert!((w[1].smooth - w[0].smooth).abs() < 0.1);
+            }
+        }
+        /* A larger radius only shifts the count by log2(ln 1000 / ln 2) */
+        let shift: f64 = (1000f64.ln() / 2f64.ln()).log2();
+        for c in points {
+            let difference = large.escape(c).smooth - small.escape(c).smooth;
+            assert!((difference - shift).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn squared_bailout_keeps_the_abs_counts() {
+        /* The escape loop as it was, comparing |z| itself */
+        let reference = |c: C64, config: &RenderConfig| -> i32 {
+            let mut z = c;
+            let mut count: i32 = 0;
+            while z.norm() < config.threshold && count < config.interior_max_iter {
+                z = z * z + c;
+                count += 1;
+            }
+            if count >= config.interior_max_iter {
+                config.max_iter
+            } else {
+                count.min(config.max_iter - 1)
+            }
+        };
+        for threshold in [2.0, 2.5, 1000.0] {
+            let config = RenderConfig::new(4, 4).with_threshold(threshold);
+            for i in 0..120 {
+                for j in 0..80 {
+                    let c = C64::new(-2.2 + i as f64 * 0.025, -1.0 + j as f64 * 0.025);
+                    assert_eq!(config.escape(c).count, reference(c, &config));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn coordinates_stay_exact_past_i64() {
+        use num_traits::{CheckedMul, CheckedSub};
+
+        /* A 16x supersampled edge of this size, with a nine-digit
+        --half-height, is where the old Rational64 form overflowed */
+        let config = RenderConfig {
+            half_height: Rational64::new(918_818_237, 1000),
+            ..RenderConfig::new(2_068_036_930, 1_467_695_134)
+        };
+        let (x_scale, _) = plane_scales(&config);
+        let extent: i64 = config.width as i64 * 16;
+        let index: i64 = 1_800_880_781;
+        let old = Rational64::new(2 * index, extent)
+            .checked_mul(&x_scale)
+            .and_then(|x| x.checked_sub(&x_scale));
+        assert!(old.is_none());
+
+        let x: f64 = base_coordinate(index, extent, x_scale).to_f64().unwrap();
+        let expected: f64 = (2.0 * index as f64 / extent as f64 - 1.0) * x_scale.to_f64().unwrap();
+        assert!((x - expected).abs() <= 1e-12 * expected.abs());
+    }
+
+    #[test]
+    fn julia_set_of_zero_is_the_unit_disk() {
+        let config = RenderConfig::new(4, 4).with_julia(C64::new(0.0, 0.0));
+        for i in 0..=60 {
+            for j in 0..=60 {
+                let z = C64::new(-1.5 + i as f64 * 0.05, -1.5 + j as f64 * 0.05);
+                /* Orbits right at the circle take longer than max_iter to tell */
+                if (z.norm() - 1.0).abs() < 0.02 {
+                    continue;
+                }
+                assert_eq!(config.escape(z).in_set, z.norm() < 1.0, "{z}");
+            }
+        }
+    }
+
+    #[test]
+    fn deep_views_keep_neighbouring_pixels_apart() {
+        let view = RenderConfig::new(16, 9).with_view(C64::new(0.0, 1.0), 1e16);
+        let deep = view.clone().with_precision();
+        assert!(deep.deep.is_some());
+        let shallow = RenderConfig::new(16, 9).with_view(C64::new(0.0, 1.0), 1e3);
+        assert!(shallow.with_precision().deep.is_none());
+
+        /* f64 merges these rows; offsets from the exact center don't */
+        assert_eq!(view.pixel_to_complex(3, 4), view.pixel_to_complex(3, 5));
+        assert_ne!(deep.pixel_to_complex(3, 4), deep.pixel_to_complex(3, 5));
+        assert_eq!(
+            deep.plane_point(deep.pixel_to_complex(3, 0)),
+            view.pixel_to_complex(3, 0)
+        );
+    }
+
+    #[test]
+    fn fixed_point_escapes_like_f64() {
+        let deep = RenderConfig::new(4, 4)
+            .with_view(C64::new(-0.5, 0.0), 1e16)
+            .with_precision();
+        let origin = deep.deep.unwrap().point(C64::new(0.0, 0.0));
+        let shallow = RenderConfig::new(4, 4).with_smooth().with_threshold(1000.0);
+        let variants = [
+            RenderConfig {
+                formula: Formula::BurningShip,
+                ..shallow.clone()
+            },
+            RenderConfig {
+                formula: Formula::Celtic,
+                ..shallow.clone()
+            },
+            RenderConfig {
+                formula: Formula::Perpendicular,
+                ..shallow.clone()
+            },
+            RenderConfig {
+                formula: Formula::Heart,
+                ..shallow.clone()
+            },
+            RenderConfig {
+                formula: Formula::Multibrot,
+                exponent: 5.0,
+                ..shallow.clone()
+            },
+            shallow.clone().with_julia(C64::new(-0.8, 0.156)),
+            RenderConfig {
+                z0: Some(C64::new(0.0, 0.1)),
+                ..shallow.clone()
+            },
+            shallow,
+        ];
+        for config in &variants {
+            let mut differing: usize = 0;
+            for i in 0..40 {
+                for j in 0..30 {
+                    let c = C64::new(-2.0 + i as f64 * 0.075, -1.2 + j as f64 * 0.08);
+                    let exact = config.escape_in(like(&origin, c));
+                    let float = config.escape(c);
+                    /* Rounding only shows in orbits long enough for chaos to grow it */
+                    if float.count < 30 {
+                        assert_eq!(exact.count, float.count, "{c} in {config:?}");
+                        assert!((exact.smooth - float.smooth).abs() < 1e-6, "{c}");
+                    } else if exact.count != float.count {
+                        differing += 1;
+                    }
+                }
+            }
+            assert!(
+                differing <= 12,
+                "{differing} long orbits differ in {config:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn cli_args_round_trip_a_deep_view() {
+        let view = Viewport {
+            center: C64::new(-0.7436438870371587, 0.13182590420531197),
+            zoom: 3.7e13,
+            rotation: -12.345678901234567,
+        };
+        let args = view.cli_args();
+        let value = |name: &str| -> &str {
+            args.split(' ')
+                .find_map(|arg| arg.strip_prefix(name)?.strip_prefix('='))
+                .unwrap()
+        };
+        let center = parse_complex(value("--center")).unwrap();
+        assert_eq!(center.re.to_bits(), view.center.re.to_bits());
+        assert_eq!(center.im.to_bits(), view.center.im.to_bits());
+        assert_eq!(value("--zoom").parse::<f64>().unwrap(), view.zoom);
+        assert_eq!(value("--rotation").parse::<f64>().unwrap(), view.rotation);
+    }
+
+    #[test]
+    fn single_threaded_tiles_stay_on_the_calling_thread() {
+        let parallel = RenderConfig::new(48, 32).with_view(C64::new(-0.75, 0.1), 4.0);
+        let single = RenderConfig {
+            single_threaded: true,
+            ..parallel.clone()
+        };
+        let palette = default_palette();
+        let render = |config: &RenderConfig| {
+            let tiles = std::sync::Mutex::new(Vec::new());
+            tiles::render_tiles(config, &palette, 16, |tile: tiles::Tile| {
+                tiles
+                    .lock()
+                    .unwrap()
+                    .push((std::thread::current().id(), tile));
+            });
+            let mut tiles = tiles.into_inner().unwrap();
+            tiles.sort_by_key(|(_, tile)| (tile.y, tile.x));
+            tiles
+        };
+
+        let caller = std::thread::current().id();
+        let single_tiles = render(&single);
+        assert_eq!(single_tiles.len(), 6);
+        assert!(single_tiles.iter().all(|(thread, _)| *thread == caller));
+        /* Rayon's workers are never the test thread itself */
+        let parallel_tiles = render(&parallel);
+        assert!(parallel_tiles.iter().all(|(thread, _)| *thread != caller));
+        for ((_, a), (_, b)) in single_tiles.iter().zip(&parallel_tiles) {
+            assert_eq!(a.pixels, b.pixels);
+        }
+    }
+
+    #[test]
+    fn steps_count_past_the_palette_clamp() {
+        let config = RenderConfig {
+            max_iter: 100,
+            interior_max_iter: 1000,
+            ..RenderConfig::new(4, 4)
+        };
+        let inside = config.escape(C64::new(0.0, 0.0));
+        assert!(inside.in_set);
+        assert_eq!((inside.count, inside.steps), (100, 1000));
+
+        /* Just past the cusp at 0.25 escapes slowly, after max_iter */
+        let late = config.escape(C64::new(0.2501, 0.0));
+        assert!(!late.in_set);
+        assert_eq!(late.count, 99);
+        assert!((100..1000).contains(&late.steps), "{}", late.steps);
+
+        let early = config.escape(C64::new(1.0, 0.0));
+        assert_eq!((early.count, early.steps), (1, 1));
+    }
+
+    #[test]
+    fn work_map_shows_iterations_past_max_iter() {
+        let config = RenderConfig {
+            max_iter: 100,
+            interior_max_iter: 1000,
+            ..RenderConfig::new(4, 4).with_view(C64::new(-0.1, 0.0), 10.0)
+        };
+        for h in 0..4 {
+            for w in 0..4 {
+                let point = config.pixel_to_complex(w, h);
+                assert_eq!(config.escape(point).count, config.max_iter);
+                assert_eq!(config.iterations_performed(point), 1000);
+            }
+        }
+        let smooth = config.clone().with_smooth();
+        let outside = C64::new(1.0, 0.0);
+        assert_eq!(
+            smooth.iterations_performed(outside),
+            1 + SMOOTH_EXTRA_ITERATIONS
+        );
+    }
+
+    #[test]
+    fn decimal_centers_report_exponents_out_of_range() {
+        let view = RenderConfig::new(16, 9).with_view(C64::new(0.0, 1.0), 1e16);
+        let exact = view
+            .clone()
+            .with_decimal_center(Some("0.0"), Some("1.000000000000000000001"))
+            .unwrap();
+        assert!(exact.deep.is_some());
+        for digits in ["1e99999999999999999999", "1e-99999999999999999999"] {
+            let error = view
+                .clone()
+                .with_decimal_center(Some(digits), None)
+                .unwrap_err();
+            assert!(error.contains("out of range"), "{error}");
+        }
+    }
+
+    #[test]
+    fn absurd_decimal_centers_fail_fast() {
+        let view = RenderConfig::new(16, 9).with_view(C64::new(0.0, 1.0), 1e16);
+        let start = std::time::Instant::now();
+        for digits in ["1e4000000000", "1e-4000000000", "-0.5e-100000"] {
+            let error = view
+                .clone()
+                .with_decimal_center(Some(digits), None)
+                .unwrap_err();
+            assert!(error.contains("too far"), "{error}");
+        }
+        /* Validation runs first, so a bad view never reaches the fixed point */
+        let infinite = RenderConfig::new(16, 9).with_view(C64::new(f64::INFINITY, 1.0), 1e16);
+        let error = infinite
+            .with_decimal_center(Some("1e4000000000"), None)
+            .unwrap_err();
+        assert!(error.contains("finite"), "{error}");
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn deep_periods_test_the_plane_point() {
+        let deep = |center: C64| {
+            RenderConfig::new(16, 9)
+                .with_view(center, 1e16)
+                .with_precision()
+        };
+        /* Pixel points are offsets near 0, on the cardioid's edge */
+        let bulb = deep(C64::new(-1.0, 0.0));
+        assert!(bulb.deep.is_some());
+        assert_eq!(
+            interior::period(&bulb, bulb.pixel_to_complex(3, 4), 10),
+            Some(2)
+        );
+        let cardioid = deep(C64::new(-0.1, 0.0));
+        assert_eq!(
+            interior::period(&cardioid, cardioid.pixel_to_complex(3, 4), 10),
+            Some(1)
+        );
+        let outside = deep(C64::new(0.3, 0.0));
+        assert_eq!(
+            interior::period(&outside, outside.pixel_to_complex(3, 4), 10),
+            None
+        );
+    }
+
+    #[test]
+    fn timed_escape_pass_keeps_the_counts() {
+        let view = RenderConfig::new(40, 24).with_view(C64::new(-0.75, 0.1), 3.0);
+        let symmetric = RenderConfig::new(40, 24)
+            .with_julia(C64::new(-0.8, 0.156))
+            .with_view(C64::new(0.0, 0.0), 1.0);
+        for config in [&view, &symmetric] {
+            let timed = get_divergence_vel_timed(config);
+            assert_eq!(timed.len(), config.height as usize);
+            let counts: Vec<Vec<i32>> = timed.iter().map(|(row, _)| row.clone()).collect();
+            assert_eq!(counts, get_divergence_vel(config));
+            assert!(timed.iter().any(|(_, time)| *time > Duration::ZERO));
+        }
+    }
+
+    #[test]
+    fn gradient_blends_between_whole_counts() {
+        let config = RenderConfig::new(4, 4);
+        let palette: Palette = default_palette();
+        let whole: Rgb<u8> = gradient(40.0, &config, &palette);
+        let between: Rgb<u8> = gradient(40.5, &config, &palette);
+        assert_ne!(whole, between);
+        let position: NotNan<f64> = NotNan::try_from(config.count_position(40.5)).unwrap();
+        assert_eq!(between, palette.get_color(position));
+    }
+}
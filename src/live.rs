@@ -0,0 +1,138 @@
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::time::Duration;
+
+use rayon::ThreadPool;
+use tungstenite::{Error, Message};
+
+use crate::{tiles, Palette, RenderConfig, Viewport, C64};
+
+/* Width of a preview; its height follows the configured aspect ratio */
+const PREVIEW_WIDTH: i32 = 512;
+/* Tiles go out as they finish, so smaller ones show progress sooner */
+const PREVIEW_TILE_PIXELS: i32 = 64;
+/* How long a read waits for the client before finished tiles are sent */
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Accepts WebSocket clients on `port`, each on its own thread, until the
+/// process is killed.
+///
+/// A client sends text messages `<re> <im> <zoom>` naming the view it wants.
+/// Each one starts a preview render and cancels the previous one, which stops
+/// after the tiles it has in progress. Tiles come back as binary messages:
+/// five little-endian u32s (the request's generation, counting from 1, then
+/// x, y, width and height in pixels) followed by width * height RGB triples.
+/// Tiles of an older generation can still arrive after a new request and
+/// should be dropped. A request that can't be rendered gets a text reply
+/// starting with "error:".
+pub fn run(config: &RenderConfig, palette: &Palette, port: u16) {
+    let listener = TcpListener::bind(("0.0.0.0", port)).unwrap();
+    /* The tile server keeps a worker of the global pool waiting for requests */
+    let pool = &rayon::ThreadPoolBuilder::new().build().unwrap();
+    eprintln!("serving live previews on ws://localhost:{port}/");
+    std::thread::scope(|scope| {
+        for stream in listener.incoming().flatten() {
+            scope.spawn(move || session(config, palette, pool, stream));
+        }
+    });
+}
+
+fn session(config: &RenderConfig, palette: &Palette, pool: &ThreadPool, stream: TcpStream) {
+    /* A short read timeout lets one thread both listen and send tiles */
+    if stream.set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+    let Ok(mut socket) = tungstenite::accept(stream) else {
+        return;
+    };
+    let generation = AtomicU32::new(0);
+    let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+
+    std::thread::scope(|scope| {
+        'session: loop {
+            match socket.read() {
+                Ok(Message::Text(text)) => match preview_config(config, text.as_str()) {
+                    Ok(preview) => {
+                        let current = generation.fetch_add(1, Ordering::SeqCst) + 1;
+                        let (generation, sender) = (&generation, sender.clone());
+                        scope.spawn(move || {
+                            pool.install(|| {
+                                render_preview(&preview, palette, current, generation, sender)
+                            })
+                        });
+                    }
+                    Err(e) => {
+                        if socket.send(Message::text(format!("error: {e}"))).is_err() {
+                            break;
+                        }
+                    }
+                },
+                Ok(_) => {}
+                Err(Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(_) => break,
+            }
+            for tile in receiver.try_iter() {
+                if socket.send(Message::binary(tile)).is_err() {
+                    break 'session;
+                }
+            }
+        }
+        /* Nobody is listening any more, so stop whatever is still rendering */
+        generation.fetch_add(1, Ordering::SeqCst);
+    });
+}
+
+fn preview_config(config: &RenderConfig, request: &str) -> Result<RenderConfig, String> {
+    let numbers: Vec<f64> = request
+        .split_whitespace()
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()
+        .ok_or_else(|| format!("'{request}' is not three numbers <re> <im> <zoom>"))?;
+    let [re, im, zoom] = numbers[..] else {
+        return Err(format!("'{request}' is not three numbers <re> <im> <zoom>"));
+    };
+    let preview = RenderConfig {
+        width: PREVIEW_WIDTH,
+        height: (PREVIEW_WIDTH as i64 * config.height as i64 / config.width as i64).max(1) as i32,
+        viewport: Some(Viewport {
+            center: C64::new(re, im),
+            zoom,
+            rotation: config.viewport.map_or(0.0, |v| v.rotation),
+        }),
+        deep: None,
+        ..config.clone()
+    };
+    preview.validate()?;
+    Ok(preview.with_precision())
+}
+
+fn render_preview(
+    preview: &RenderConfig,
+    palette: &Palette,
+    current: u32,
+    generation: &AtomicU32,
+    sender: Sender<Vec<u8>>,
+) {
+    tiles::render_tiles_while(
+        preview,
+        palette,
+        PREVIEW_TILE_PIXELS,
+        || generation.load(Ordering::SeqCst) == current,
+        |tile: tiles::Tile| {
+            let header = [
+                current,
+                tile.x as u32,
+                tile.y as u32,
+                tile.width as u32,
+                tile.height as u32,
+            ];
+            let mut message: Vec<u8> = header.iter().flat_map(|v| v.to_le_bytes()).collect();
+            message.extend(tile.pixels.iter().flat_map(|pixel| pixel.0));
+            /* The session may have ended; its tiles are simply dropped */
+            sender.send(message).unwrap_or_default();
+        },
+    );
+}
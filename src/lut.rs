@@ -0,0 +1,64 @@
+use ordered_float::NotNan;
+
+use crate::color::MathyColor;
+use crate::{Palette, RenderConfig};
+
+/// Palette colors computed once so pixels skip the search and interpolation
+/// in `Palette::get_mathy_color`.
+pub struct ColorLut {
+    colors: Vec<MathyColor<f64>>,
+}
+
+impl ColorLut {
+    /// One entry per escape count from 0 to max_iter, placed on the palette as
+    /// `config` places whole counts. Exact for integer counts.
+    pub fn counts(palette: &Palette, config: &RenderConfig) -> ColorLut {
+        ColorLut {
+            colors: (0..=config.max_iter)
+                .map(|velocity: i32| {
+                    palette.get_mathy_color(
+                        NotNan::try_from(config.count_position(velocity as f64)).unwrap(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// One entry per escape count from 0 to max_iter at the count's palette
+    /// position in `positions`, such as `histogram::equalized_positions` gives.
+    pub fn from_positions(palette: &Palette, positions: &[f64]) -> ColorLut {
+        ColorLut {
+            colors: positions
+                .iter()
+                .map(|position: &f64| palette.get_mathy_color(NotNan::try_from(*position).unwrap()))
+                .collect(),
+        }
+    }
+
+    /// `entries` colors at evenly spaced positions over [0, 1], for continuous
+    /// positions such as smooth coloring. A position is snapped to the nearest
+    /// entry, so it can be off by up to 1 / (2 * (entries - 1)): at 4096 entries
+    /// that is below what an 8-bit channel resolves on most palettes, while a
+    /// few hundred entries can show bands across slow gradients.
+    pub fn fine(palette: &Palette, entries: usize) -> ColorLut {
+        assert!(entries >= 2, "a color LUT needs at least two entries");
+        ColorLut {
+            colors: (0..entries)
+                .map(|i: usize| {
+                    palette
+                        .get_mathy_color(NotNan::try_from(i as f64 / (entries - 1) as f64).unwrap())
+                })
+                .collect(),
+        }
+    }
+
+    pub fn count(&self, velocity: i32) -> MathyColor<f64> {
+        self.colors[velocity as usize]
+    }
+
+    pub fn position(&self, position: f64) -> MathyColor<f64> {
+        let last = self.colors.len() - 1;
+        let index = (position.clamp(0.0, 1.0) * last as f64).round() as usize;
+        self.colors[index]
+    }
+}
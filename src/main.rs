@@ -1,222 +1,1978 @@
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use rayon::prelude::*;
-use std::collections::{BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Cursor, Write};
 
-use image::{ImageBuffer, Rgb};
-use lerp::Lerp;
-use num_complex::{Complex64, ComplexFloat};
-use num_rational::{Ratio, Rational64};
-use num_traits::{Float, ToPrimitive};
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb};
+use num_rational::Rational64;
+use num_traits::ToPrimitive;
 use ordered_float::NotNan;
+use serde::Deserialize;
 
-type C64 = Complex64;
-const ITERMAX: i32 = 100;
+mod bookmarks;
+mod config_file;
+mod indexed;
+mod sheet;
+mod timing;
 
-#[derive(Debug, Copy, Clone)]
-struct MathyColor<F> {
-    r: F,
-    g: F,
-    b: F,
+use mandelbrot::color::Transfer;
+use mandelbrot::color_expr::ColorExpr;
+use mandelbrot::palette::parse_hex_color;
+use mandelbrot::pixels::{Coordinates, Order};
+use mandelbrot::{
+    area, ascii, bench, bloom, buddhabrot, build_palette, checkpoint, compare, contour, cycle,
+    deep, diff, distance, fill_pixels, get_divergence_rows, get_divergence_vel,
+    get_divergence_vel_masked, get_divergence_vel_timed, get_supersampled_colors, glitch, gradient,
+    histogram, interior, layers, lut, map_rows, overlay, parse_complex, pixel_format, rays,
+    render_mask, stats, subsample, supersampler, sweep, target, tiles, BlendSpace, EscapeResult,
+    Formula, MathyColor, Palette, PaletteName, RenderConfig, Viewport, C64, DOWNSCALE_FILTER,
+    DOWNSCALE_REACH, ITERMAX, LOCATIONS, STDOUT_PATH,
+};
+
+use config_file::ConfigFile;
+
+/* Rows computed per batch when streaming to the PNG encoder */
+const STREAM_BAND_ROWS: i32 = 64;
+/* Below this bailout radius smooth coloring still shows visible banding */
+const SMOOTH_MIN_THRESHOLD: f64 = 100.0;
+/* Brightness of the cells whose escaped z has Im <= 0 */
+const BINARY_DECOMPOSITION_SHADE: f64 = 0.5;
+/* --ssaa renders above this many pixels get a memory warning */
+const SSAA_WARN_PIXELS: i64 = 100_000_000;
+
+#[derive(Parser, Debug, Clone)]
+#[command(about = "Render the Mandelbrot set to a PNG")]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// TOML file with default settings [default: mandelbrot.toml if present]
+    #[arg(long, value_name = "PATH")]
+    config: Option<std::path::PathBuf>,
+
+    /// Write rows to the encoder as they finish instead of buffering the whole image
+    #[arg(long)]
+    stream: bool,
+
+    /// Add a glow around bright regions with the given intensity
+    #[arg(long, value_name = "INTENSITY", conflicts_with = "stream")]
+    bloom: Option<f64>,
+
+    /// Grade the finished colors with a gamma per channel, as R,G,B or one value
+    /// for all three; above 1 brightens a channel, below 1 darkens it
+    #[arg(long, value_name = "R,G,B", value_parser = parse_channel_gamma, conflicts_with_all = ["stream", "cycle_frames"])]
+    channel_gamma: Option<[f64; 3]>,
+
+    /// Average an N x N grid of samples per pixel
+    #[arg(long, value_name = "N", default_value_t = 1, conflicts_with = "stream")]
+    supersample: i32,
+
+    /// Supersample only pixels the distance estimate puts within a pixel of the
+    /// set, which keeps tiny minibrots at a fraction of the cost; formulas
+    /// without an estimate supersample every escaping pixel
+    #[arg(long, requires = "supersample")]
+    de_adaptive: bool,
+
+    /// Placement of the --supersample subsamples within each pixel
+    #[arg(long, value_enum, default_value_t = subsample::Pattern::Grid)]
+    subsample_pattern: subsample::Pattern,
+
+    /// Reconstruction filter that weighs the --supersample subsamples
+    #[arg(long, value_enum, default_value_t = subsample::Filter::Box)]
+    aa_filter: subsample::Filter,
+
+    /// Channels and bit depth of the saved image
+    #[arg(long, value_enum, default_value_t = pixel_format::PixelFormat::Rgb8, conflicts_with_all = ["indexed", "stream", "cycle_frames"])]
+    pixel_format: pixel_format::PixelFormat,
+
+    /// Color smooth and log-mapped positions from a table of N palette samples
+    /// instead of interpolating the palette per pixel
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(2..))]
+    lut_size: Option<u32>,
+
+    /// Render N times larger in each direction and Lanczos-resample down on save.
+    /// Costs N^2 times the pixels, and with them the memory and time
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(i32).range(1..), conflicts_with_all = ["stream", "cycle_frames", "escape_map", "height_map"])]
+    ssaa: i32,
+
+    /// Average supersamples in linear light instead of gamma-encoded values
+    #[arg(long)]
+    linear_aa: bool,
+
+    /// Transfer curve used to linearize colors: an exponent such as 2.2, or srgb
+    #[arg(long, value_parser = parse_transfer, default_value = "2.2")]
+    gamma: Transfer,
+
+    /// Color space for blending palette stops and averaging supersamples
+    #[arg(long, value_enum, default_value_t = BlendSpace::Rgb, conflicts_with = "linear_aa")]
+    blend_space: BlendSpace,
+
+    /// Also write the outline of every --svg-levels count as an SVG path
+    #[arg(long, value_name = "PATH", conflicts_with = "stream")]
+    svg: Option<String>,
+
+    /// Escape counts to outline with --svg, each stroked in the palette color
+    /// just outside it, up to --max-iter; the default traces the boundary of
+    /// the set
+    #[arg(long, value_name = "COUNTS", value_delimiter = ',', value_parser = clap::value_parser!(i32).range(1..), requires = "svg")]
+    svg_levels: Vec<i32>,
+
+    /// Also write the raw escape counts as a grayscale PNG
+    #[arg(long, value_name = "PATH", conflicts_with = "stream")]
+    escape_map: Option<String>,
+
+    /// Write the escape map with 16 bits per pixel instead of 8
+    #[arg(long)]
+    escape_map_16bit: bool,
+
+    /// Shade used for points that never escape in the escape map
+    #[arg(long, value_enum, default_value_t = Interior::White)]
+    escape_map_interior: Interior,
+
+    /// How to color points that never escape
+    #[arg(long, value_enum, default_value_t = interior::Coloring::Solid, conflicts_with_all = ["stream", "tile_size", "cycle_frames"])]
+    interior_coloring: interior::Coloring,
+
+    /// Colors to map escape counts onto
+    #[arg(long, value_enum, default_value_t = PaletteName::Default, conflicts_with = "cycle_frames")]
+    palette: PaletteName,
+
+    /// Read the palette from a GIMP .ggr gradient instead; the set takes the color at its end
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["palette", "cycle_frames"])]
+    palette_file: Option<std::path::PathBuf>,
+
+    /// Use an inline gradient as the palette, e.g. "0%:#000000, 30%:#ff8800, 100%:#ffffff";
+    /// the set takes the color at 100%
+    #[arg(long, value_name = "STOPS", value_parser = parse_gradient, conflicts_with_all = ["palette", "palette_file", "cycle_frames"])]
+    gradient: Option<Palette>,
+
+    /// Easing exponent per palette segment, or one for all; 1 blends linearly, higher holds each stop's color longer
+    #[arg(long, value_name = "EXP,...", value_delimiter = ',', value_parser = parse_easing)]
+    palette_easing: Vec<f64>,
+
+    /// Iteration formula to render
+    #[arg(long, value_enum, default_value_t = Formula::Mandelbrot)]
+    formula: Formula,
+
+    /// Power d in the multibrot formula; may be negative or fractional
+    #[arg(long, default_value_t = 2.0, allow_hyphen_values = true)]
+    exponent: f64,
+
+    /// Frame a well-known region of the set
+    #[arg(long, global = true, value_parser = clap::builder::PossibleValuesParser::new(LOCATIONS.iter().map(|l| l.0)))]
+    location: Option<String>,
+
+    /// Center of the view, e.g. -0.75+0.1i (overrides --location)
+    #[arg(long, global = true, value_parser = parse_complex, allow_hyphen_values = true)]
+    center: Option<C64>,
+
+    /// Real part of the center as a decimal string of any length
+    #[arg(long, global = true, value_parser = parse_decimal, allow_hyphen_values = true, conflicts_with = "center")]
+    center_re: Option<String>,
+
+    /// Imaginary part of the center as a decimal string of any length
+    #[arg(long, global = true, value_parser = parse_decimal, allow_hyphen_values = true, conflicts_with = "center")]
+    center_im: Option<String>,
+
+    /// Magnification relative to the full view (overrides --location)
+    #[arg(long, global = true)]
+    zoom: Option<f64>,
+
+    /// Turn the view counterclockwise about its center by this many degrees
+    #[arg(
+        long,
+        global = true,
+        value_name = "DEGREES",
+        allow_hyphen_values = true
+    )]
+    rotation: Option<f64>,
+
+    /// Frame the whole Mandelbrot set with a small margin, fitted to the image aspect
+    #[arg(long, global = true, conflicts_with_all = ["location", "center", "center_re", "center_im", "zoom", "rotation"])]
+    auto_frame: bool,
+
+    /// Frame this rectangle of the plane, e.g. -2.5,1,-1.2,1.2; a box whose shape
+    /// doesn't match the image is fitted whole with extra plane around it
+    #[arg(long, global = true, value_name = "RE_MIN,RE_MAX,IM_MIN,IM_MAX", value_parser = parse_bounds, allow_hyphen_values = true, conflicts_with_all = ["location", "center", "center_re", "center_im", "zoom", "rotation", "auto_frame"])]
+    bounds: Option<[f64; 4]>,
+
+    /// Frame a view saved with `bookmark add`
+    #[arg(long, global = true, value_name = "NAME", conflicts_with_all = ["location", "center", "center_re", "center_im", "zoom", "rotation", "auto_frame", "bounds"])]
+    bookmark: Option<String>,
+
+    /// Half the image height in plane units at zoom 1, as a decimal like 1.12
+    #[arg(long, global = true, value_parser = parse_ratio, default_value = "1.12")]
+    half_height: Rational64,
+
+    /// What to render
+    #[arg(long, value_enum, default_value_t = Mode::Escape)]
+    mode: Mode,
+
+    /// Random orbits traced in the buddhabrot and nebulabrot modes; the default
+    /// is smooth at 1920x1080, and grain falls with the square root of this
+    #[arg(long, default_value_t = 20_000_000)]
+    samples: u64,
+
+    /// Iteration cap for the buddhabrot mode
+    #[arg(long, default_value_t = 1000)]
+    buddhabrot_iter: i32,
+
+    /// Leave out orbits that escape in fewer steps; 0 keeps them all, while a few
+    /// hundred leaves only the filaments near the boundary
+    #[arg(long, value_name = "STEPS", default_value_t = 0)]
+    buddhabrot_min_iter: i32,
+
+    /// Draw orbits more often near the set boundary, where the long ones start;
+    /// the same image in fewer samples, most of all with --buddhabrot-min-iter
+    #[arg(long)]
+    importance_sampling: bool,
+
+    /// Iteration caps for the red, green and blue nebulabrot passes
+    #[arg(long, value_name = "R,G,B", value_parser = parse_iter_caps, default_value = "5000,500,50")]
+    nebula_iters: [i32; 3],
+
+    /// Seed for the random orbit sampling
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Palette position as an expression of t (normalized count), z (final |z|) and n (count)
+    #[arg(long, value_name = "EXPR", value_parser = ColorExpr::parse, conflicts_with_all = ["stream", "supersample"])]
+    color_expr: Option<ColorExpr>,
+
+    /// Render the Julia set for this constant, e.g. -0.8+0.156i
+    #[arg(long, value_parser = parse_complex, allow_hyphen_values = true)]
+    julia: Option<C64>,
+
+    /// Start every Mandelbrot orbit at this z instead of at c. Without it the
+    /// first z is c, the same as starting from 0 one step earlier, which is
+    /// what all other renders use
+    #[arg(long, value_name = "POINT", value_parser = parse_complex, allow_hyphen_values = true, conflicts_with = "julia")]
+    z0: Option<C64>,
+
+    /// Most rows handed to a worker at once; small values balance uneven views better
+    #[arg(long, value_name = "ROWS")]
+    chunk_rows: Option<usize>,
+
+    /// Save finished rows here while rendering, and resume from it after an interruption
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "tile_size", "cycle_frames", "supersample", "smooth", "color_expr", "coverage_aa", "log_map"])]
+    checkpoint: Option<std::path::PathBuf>,
+
+    /// Start from the escape counts in this escape map (--escape-map, same size,
+    /// white interior) and recompute only pixels at --refine-min-count or above;
+    /// the rest keep the map's counts
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "tile_size", "cycle_frames", "checkpoint", "supersample", "smooth", "color_expr", "coverage_aa", "log_map"])]
+    refine_from: Option<std::path::PathBuf>,
+
+    /// Lowest prior count --refine-from recomputes; the default, --max-iter,
+    /// redoes only the pixels that never escaped, e.g. after raising
+    /// --interior-max-iter
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(i32).range(0..), requires = "refine_from")]
+    refine_min_count: Option<i32>,
+
+    /// Rows computed between checkpoint saves
+    #[arg(long, value_name = "ROWS", default_value_t = 64, value_parser = clap::value_parser!(i32).range(1..))]
+    checkpoint_rows: i32,
+
+    /// Compute escape counts on the main thread only (always on under WASM)
+    #[arg(long)]
+    single_threaded: bool,
+
+    /// Order in which pixels are handed to the worker threads
+    #[arg(long, value_enum, default_value_t = Order::RowMajor)]
+    order: Order,
+
+    /// How the escape-count pass finds each pixel's point; stepped skips the
+    /// exact rational coordinates for quicker previews
+    #[arg(long, value_enum, default_value_t = Coordinates::Exact)]
+    coordinates: Coordinates,
+
+    /// Fewest pixels of a row handed to a worker at once
+    #[arg(long, value_name = "PIXELS", default_value_t = 1)]
+    chunk_pixels: usize,
+
+    /// Color by the continuous (normalized) iteration count instead of whole counts
+    #[arg(long, conflicts_with = "stream")]
+    smooth: bool,
+
+    /// Map escape values t in [0, 1] to log_B(1 + (B - 1) t), giving the bands
+    /// near the set more of the palette; larger B compresses more
+    #[arg(long, value_name = "B", value_parser = parse_log_base, conflicts_with_all = ["stream", "histogram", "cycle_frames"])]
+    log_map: Option<f64>,
+
+    /// Give every escape count up to N the palette's first color and spread the rest over the remaining counts
+    #[arg(long, value_name = "N", default_value_t = 0, value_parser = clap::value_parser!(i32).range(0..), conflicts_with_all = ["histogram", "cycle_frames"])]
+    min_count: i32,
+
+    /// Raise each normalized escape value to this power before the palette
+    /// lookup; above 1 pushes the bands toward the set, below 1 away from it
+    #[arg(long, value_name = "POWER", default_value_t = 1.0, value_parser = parse_bias, conflicts_with_all = ["histogram", "cycle_frames"])]
+    bias: f64,
+
+    /// Shift every escaping pixel's palette position by this much, wrapping
+    /// past the end of the palette, to line palettes up across renders; with
+    /// --cycle-frames it is where the cycle starts
+    #[arg(
+        long,
+        value_name = "OFFSET",
+        default_value_t = 0.0,
+        allow_hyphen_values = true,
+        conflicts_with = "histogram"
+    )]
+    color_offset: f64,
+
+    /// Escape radius; smooth coloring works best with large values such as 1000
+    #[arg(long, default_value_t = 2.0)]
+    threshold: f64,
+
+    /// Highest escape count; points that haven't escaped after this many steps
+    /// count as inside, and the palette is spread over 0 to this
+    #[arg(long, value_name = "N", default_value_t = ITERMAX, value_parser = clap::value_parser!(i32).range(1..))]
+    max_iter: i32,
+
+    /// Keep iterating points that haven't escaped by --max-iter up to this many steps before calling them inside; late escapes take the last exterior color [default: --max-iter]
+    #[arg(long, value_name = "STEPS", value_parser = clap::value_parser!(i32).range(1..))]
+    interior_max_iter: Option<i32>,
+
+    /// Render in square tiles of this many pixels
+    #[arg(long, value_name = "PIXELS", value_parser = clap::value_parser!(i32).range(1..), conflicts_with_all = ["stream", "supersample", "bloom", "channel_gamma", "color_expr", "escape_map"])]
+    tile_size: Option<i32>,
+
+    /// Output pixels each tile renders past its edges for --ssaa, so the
+    /// downscale filter sees the same neighbors as in a whole-image render
+    #[arg(long, value_name = "PIXELS", default_value_t = DOWNSCALE_REACH, value_parser = clap::value_parser!(i32).range(0..), requires = "tile_size")]
+    tile_apron: i32,
+
+    /// Antialias edges from the distance estimate instead of extra samples
+    #[arg(long, conflicts_with_all = ["stream", "supersample", "color_expr"])]
+    coverage_aa: bool,
+
+    /// Also write the smooth iteration count as a 16-bit grayscale height map
+    #[arg(long, value_name = "PATH", conflicts_with = "stream")]
+    height_map: Option<String>,
+
+    /// Also write a grayscale map of how many iterations each pixel cost
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "ssaa"])]
+    work_map: Option<String>,
+
+    /// Debugging aid: write row,microseconds to this CSV, timed within the
+    /// render's own escape pass
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "ssaa", "checkpoint", "refine_from"])]
+    row_timing: Option<String>,
+
+    /// Also write a mask that is white inside the set and black outside
+    #[arg(long, value_name = "PATH", conflicts_with = "stream")]
+    mask: Option<String>,
+
+    /// Multiplier applied to the height map before it is clipped to 16 bits
+    #[arg(long, default_value_t = 1.0)]
+    height_scale: f64,
+
+    /// Paint pixels that probably lost precision in this color (RRGGBB)
+    #[arg(long, value_name = "COLOR", value_parser = parse_hex_color, conflicts_with_all = ["stream", "supersample"])]
+    flag_glitches: Option<Rgb<u8>>,
+
+    /// Draw the orbit of this point over the image, e.g. -0.1+0.8i
+    #[arg(long, value_name = "POINT", value_parser = parse_complex, allow_hyphen_values = true, conflicts_with_all = ["stream", "tile_size", "julia"])]
+    orbit: Option<C64>,
+
+    /// Paint pixels that ran out of iterations without being shown to lie in the set (RRGGBB)
+    #[arg(long, value_name = "COLOR", value_parser = parse_hex_color, conflicts_with_all = ["stream", "tile_size", "cycle_frames"])]
+    flag_undecided: Option<Rgb<u8>>,
+
+    /// Line color for --orbit (RRGGBB)
+    #[arg(long, value_name = "COLOR", value_parser = parse_hex_color, default_value = "ff3030")]
+    orbit_color: Rgb<u8>,
+
+    /// Draw the Mandelbrot set's external rays at these angles in turns, e.g.
+    /// 1/3,2/3 for the two rays landing at the root of the period-2 bulb
+    #[arg(long, value_name = "ANGLES", value_delimiter = ',', value_parser = rays::parse_angle, conflicts_with_all = ["stream", "tile_size", "julia", "z0"])]
+    rays: Vec<rays::Angle>,
+
+    /// Line color for --rays (RRGGBB)
+    #[arg(long, value_name = "COLOR", value_parser = parse_hex_color, default_value = "30a0ff")]
+    ray_color: Rgb<u8>,
+
+    /// Animate the palette over this many frames instead of rendering a still
+    #[arg(long, value_name = "FRAMES", conflicts_with_all = ["stream", "tile_size", "supersample", "smooth", "coverage_aa", "color_expr"])]
+    cycle_frames: Option<u32>,
+
+    /// Animate the Julia constant along this path instead of rendering a
+    /// still: circle:CENTER,RADIUS, line:FROM,TO or list:C1,C2,... Each frame
+    /// is colored like a still render from its escape counts
+    #[arg(long, value_name = "PATH", value_parser = sweep::parse_path, allow_hyphen_values = true, conflicts_with_all = ["julia", "z0", "rays", "cycle_frames", "stream", "tile_size", "supersample", "smooth", "coverage_aa", "color_expr", "mode", "bloom", "channel_gamma", "log_map", "pixel_format", "ssaa", "indexed", "ascii", "escape_map", "svg", "height_map", "work_map", "row_timing", "mask", "stats_json", "checkpoint", "refine_from", "timing"])]
+    julia_path: Option<sweep::Path>,
+
+    /// Frames rendered along --julia-path
+    #[arg(long, value_name = "FRAMES", default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..), requires = "julia_path")]
+    julia_frames: u32,
+
+    /// Write the palette or Julia animation as one GIF or as numbered PNG frames
+    #[arg(long, value_enum, default_value_t = cycle::Format::Gif)]
+    cycle_format: cycle::Format,
+
+    /// Spread the palette evenly over the escaping pixels (histogram equalization)
+    #[arg(long, conflicts_with_all = ["stream", "tile_size", "supersample", "smooth", "coverage_aa", "color_expr", "bloom", "channel_gamma", "cycle_frames"])]
+    histogram: bool,
+
+    /// Print how long each phase of the render took
+    #[arg(long)]
+    timing: bool,
+
+    /// Write render statistics as JSON to this path, or - for stdout
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stream", "tile_size", "cycle_frames"])]
+    stats_json: Option<String>,
+
+    /// Image width in pixels (overrides the config file) [default: 3840]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    width: Option<i32>,
+
+    /// Image height in pixels (overrides the config file) [default: 2160]
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    height: Option<i32>,
+
+    /// Output file, or - to write the image to stdout (overrides the config file)
+    #[arg(long, allow_hyphen_values = true)]
+    out: Option<String>,
+
+    /// Image format; by default taken from the file extension, PNG on stdout
+    #[arg(long, value_enum, conflicts_with = "stream")]
+    out_format: Option<OutFormat>,
+
+    /// Write an indexed PNG when the image has at most 256 colors
+    #[arg(long, conflicts_with_all = ["out_format", "stream"])]
+    indexed: bool,
+
+    /// Print the view to the terminal in shade characters instead of writing an
+    /// image, sized from COLUMNS and LINES
+    #[arg(long, conflicts_with_all = ["out", "mode", "stream", "tile_size", "cycle_frames"])]
+    ascii: bool,
+
+    /// Resolve and validate every option, print the result and exit without rendering
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(ValueEnum, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+enum Mode {
+    /// Color each pixel by how fast it escapes
+    Escape,
+    /// Density of escaping orbits
+    Buddhabrot,
+    /// Three buddhabrot passes stacked as RGB
+    Nebulabrot,
+    /// Escape coloring, darkened where the escaped z lies below the real axis
+    BinaryDecomposition,
+    /// Escape count plus the turn of the escaped z, which twists the bands into
+    /// spirals around minibrots
+    Spiral,
+    /// Experimental: red and green from when Re(z) and Im(z) each pass --threshold
+    AxisEscape,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Print the complex number a pixel maps to, without rendering
+    Inspect {
+        #[arg(long)]
+        x: i32,
+        #[arg(long)]
+        y: i32,
+        #[arg(long, default_value_t = 3840)]
+        width: i32,
+        #[arg(long, default_value_t = 2160)]
+        height: i32,
+        /// Also print whether the point escapes, after how many steps, and the last z
+        #[arg(long)]
+        escape: bool,
+    },
+    /// List the built-in palettes
+    Palettes {
+        /// Also write a PNG with a labeled strip of each palette
+        #[arg(long, value_name = "PATH")]
+        sheet: Option<String>,
+    },
+    /// Save, remove or list named views in mandelbrot-bookmarks.json
+    Bookmark {
+        #[command(subcommand)]
+        action: BookmarkAction,
+    },
+    /// Estimate the area of the set within the view by random sampling
+    Area {
+        /// Number of random points to test
+        #[arg(long, default_value_t = 1_000_000)]
+        samples: u64,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Time the escape counts of a fixed benchmark scene and print the
+    /// throughput. View, size and formula options are ignored; scheduling
+    /// options such as --order and --coordinates apply
+    Bench {
+        /// Timed runs, after one untimed warm-up run
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+        runs: u32,
+        /// Exit with status 1 if the fastest run renders fewer megapixels per
+        /// second than this
+        #[arg(long, value_name = "MPIXELS")]
+        budget: Option<f64>,
+    },
+    /// Search for a detailed spot to zoom into by following the set boundary
+    /// from the current view, and print its center and zoom
+    FindTarget {
+        /// Zoom steps to take, each magnifying 4x
+        #[arg(long, default_value_t = 12)]
+        steps: u32,
+    },
+    /// Render two settings side by side, the left half with one and the right
+    /// half with the other. Each side starts from the main settings
+    Compare {
+        /// Config file for the left half; its width, height and output are ignored
+        #[arg(long, value_name = "PATH")]
+        left: Option<std::path::PathBuf>,
+        /// Config file for the right half; its width, height and output are ignored
+        #[arg(long, value_name = "PATH")]
+        right: Option<std::path::PathBuf>,
+        /// Palette for the left half [default: --palette]
+        #[arg(long, value_enum)]
+        left_palette: Option<PaletteName>,
+        /// Palette for the right half [default: --palette]
+        #[arg(long, value_enum)]
+        right_palette: Option<PaletteName>,
+        /// Color of the line between the halves
+        #[arg(long, value_name = "RRGGBB", default_value = "ffffff", value_parser = parse_hex_color)]
+        divider: Rgb<u8>,
+    },
+    /// Render two settings and write their per-pixel color difference,
+    /// amplified, then print the largest and mean difference. Each side starts
+    /// from the main settings
+    Diff {
+        /// Config file for the first render; its width, height and output are ignored
+        #[arg(long, value_name = "PATH")]
+        left: Option<std::path::PathBuf>,
+        /// Config file for the second render; its width, height and output are ignored
+        #[arg(long, value_name = "PATH")]
+        right: Option<std::path::PathBuf>,
+        /// Palette for the first render [default: --palette]
+        #[arg(long, value_enum)]
+        left_palette: Option<PaletteName>,
+        /// Palette for the second render [default: --palette]
+        #[arg(long, value_enum)]
+        right_palette: Option<PaletteName>,
+        /// Factor the differences are multiplied by before they are drawn
+        #[arg(long, default_value_t = 8.0)]
+        amplify: f64,
+    },
+    /// Serve map tiles at /tile/{z}/{x}/{y}.png, rendered on demand
+    #[cfg(feature = "server")]
+    Serve {
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+        /// Also accept WebSocket clients on this port for live previews
+        #[arg(long)]
+        live_port: Option<u16>,
+    },
 }
 
-impl<F> MathyColor<F>
-where
-    F: Float + ToPrimitive,
-{
-    fn new(r: F, g: F, b: F) -> Self {
-        Self { r, g, b }
+#[derive(Subcommand, Debug, Clone)]
+enum BookmarkAction {
+    /// Save the view given by --location, --center, --zoom and the like
+    Add {
+        name: String,
+    },
+    Remove {
+        name: String,
+    },
+    List,
+}
+
+/* Corners of a box that holds the whole Mandelbrot set, as (re, im) */
+const SET_BOUNDS: [(f64, f64); 2] = [(-2.0, -1.12), (0.47, 1.12)];
+/* Fraction of the set's extent left empty on each side by --auto-frame */
+const AUTO_FRAME_MARGIN: f64 = 0.05;
+/* Relative difference between --bounds and image aspect ratios that is warned about */
+const BOUNDS_ASPECT_TOLERANCE: f64 = 0.01;
+
+fn parse_decimal(s: &str) -> Result<String, String> {
+    /* Kept as text so the digits survive until a backend can use them */
+    let digits = s.trim().trim_start_matches(['+', '-']);
+    let (mantissa, exponent) = match digits.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e.strip_prefix(['+', '-']).unwrap_or(e))),
+        None => (digits, None),
+    };
+    let (int, frac) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |part: &str| part.chars().all(|ch| ch.is_ascii_digit());
+    let valid = !(int.is_empty() && frac.is_empty())
+        && all_digits(int)
+        && all_digits(frac)
+        && exponent.is_none_or(|e| !e.is_empty() && all_digits(e));
+    if !valid {
+        return Err(format!("'{s}' is not a decimal number"));
+    }
+    if exponent.is_some_and(|e| e.parse::<i64>().is_err()) {
+        return Err(format!("the exponent of '{s}' is out of range"));
     }
+    Ok(s.trim().to_string())
+}
 
-    // fn from(other: Rgb<u8>) -> Self {
-    //     Self {
-    //         r: F::from(other.0[0]).unwrap(),
-    //         g: F::from(other.0[1]).unwrap(),
-    //         b: F::from(other.0[2]).unwrap()
-    //     }
-    // }
+fn parse_ratio(s: &str) -> Result<Rational64, String> {
+    /* Exact decimal, so the default 1.12 stays the ratio 112/100 */
+    let (int, frac) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+    let digits = format!("{int}{frac}");
+    let invalid = || format!("'{s}' is not a positive decimal with at most 9 digits");
+    if digits.is_empty() || digits.len() > 9 || !digits.chars().all(|ch| ch.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let ratio = Rational64::new(digits.parse().unwrap(), 10i64.pow(frac.len() as u32));
+    if ratio <= Rational64::from_integer(0) {
+        return Err(invalid());
+    }
+    Ok(ratio)
+}
 
-    fn from_ref(other: &Rgb<u8>) -> Self {
-        Self {
-            r: F::from(other.0[0]).unwrap(),
-            g: F::from(other.0[1]).unwrap(),
-            b: F::from(other.0[2]).unwrap(),
-        }
+fn parse_log_base(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(base) if base > 1.0 && base.is_finite() => Ok(base),
+        _ => Err(format!("'{s}' is not a log base greater than 1")),
     }
+}
 
-    fn unwrap(&self) -> Rgb<u8> {
-        Rgb([
-            self.r.round().to_u8().unwrap(),
-            self.g.round().to_u8().unwrap(),
-            self.b.round().to_u8().unwrap(),
-        ])
+fn parse_bias(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(bias) if bias > 0.0 && bias.is_finite() => Ok(bias),
+        _ => Err(format!("'{s}' is not a positive bias")),
     }
 }
 
-impl<F> std::ops::Add for MathyColor<F>
-where
-    F: Float,
-{
-    type Output = Self;
-    fn add(self, rhs: Self) -> Self::Output {
-        MathyColor::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+fn parse_easing(s: &str) -> Result<f64, String> {
+    match s.trim().parse::<f64>() {
+        Ok(exponent) if exponent > 0.0 && exponent.is_finite() => Ok(exponent),
+        _ => Err(format!("'{s}' is not a positive easing exponent")),
     }
 }
 
-impl<F> std::ops::Mul<F> for MathyColor<F>
-where
-    F: Float,
-{
-    type Output = Self;
-    fn mul(self, rhs: F) -> Self::Output {
-        MathyColor::new(self.r * rhs, self.g * rhs, self.b * rhs)
+fn parse_bounds(s: &str) -> Result<[f64; 4], String> {
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|part| {
+            part.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("'{part}': {e}"))
+        })
+        .collect::<Result<_, _>>()?;
+    values.try_into().map_err(|values: Vec<f64>| {
+        format!(
+            "expected re_min,re_max,im_min,im_max, got {} values",
+            values.len()
+        )
+    })
+}
+
+fn parse_gradient(s: &str) -> Result<Palette, String> {
+    Palette::from_css(s).map_err(|e| e.to_string())
+}
+
+fn parse_channel_gamma(s: &str) -> Result<[f64; 3], String> {
+    let values: Vec<f64> = s
+        .split(',')
+        .map(|part| match part.trim().parse::<f64>() {
+            Ok(gamma) if gamma > 0.0 && gamma.is_finite() => Ok(gamma),
+            _ => Err(format!("'{part}' is not a positive gamma")),
+        })
+        .collect::<Result<_, _>>()?;
+    match values[..] {
+        [gamma] => Ok([gamma; 3]),
+        [r, g, b] => Ok([r, g, b]),
+        _ => Err(format!("expected 1 or 3 gammas, got {}", values.len())),
     }
 }
 
-#[derive(Debug, Clone)]
-struct Palette {
-    /* Collection of x, color pairs with 0 <= x <= 1 */
-    _keys: BTreeSet<NotNan<f64>>,
-    _key_map: HashMap<NotNan<f64>, Rgb<u8>>,
+fn parse_transfer(s: &str) -> Result<Transfer, String> {
+    if s.eq_ignore_ascii_case("srgb") {
+        return Ok(Transfer::Srgb);
+    }
+    match s.parse::<f64>() {
+        Ok(gamma) if gamma > 0.0 => Ok(Transfer::Gamma(gamma)),
+        _ => Err(format!("'{s}' is neither srgb nor a positive exponent")),
+    }
+}
+
+fn parse_iter_caps(s: &str) -> Result<[i32; 3], String> {
+    let caps: Vec<i32> = s
+        .split(',')
+        .map(|part| {
+            part.trim()
+                .parse::<i32>()
+                .map_err(|e| format!("'{part}': {e}"))
+        })
+        .collect::<Result<_, _>>()?;
+    caps.try_into()
+        .map_err(|caps: Vec<i32>| format!("expected 3 caps, got {}", caps.len()))
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+enum OutFormat {
+    Png,
+    Bmp,
+    Jpeg,
+    Tiff,
+    /// OpenEXR, which needs one of the 32-bit float pixel formats
+    Exr,
 }
 
-impl Palette {
-    fn new() -> Palette {
-        Palette {
-            _keys: BTreeSet::new(),
-            _key_map: HashMap::new(),
+impl OutFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            OutFormat::Png => ImageFormat::Png,
+            OutFormat::Bmp => ImageFormat::Bmp,
+            OutFormat::Jpeg => ImageFormat::Jpeg,
+            OutFormat::Tiff => ImageFormat::Tiff,
+            OutFormat::Exr => ImageFormat::OpenExr,
         }
     }
+}
 
-    fn add_col(&mut self, key: NotNan<f64>, new_color: &Rgb<u8>) {
-        self._keys.insert(key);
-        self._key_map.insert(key, new_color.to_owned());
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+enum Interior {
+    White,
+    Black,
+}
+
+fn main() {
+    let mut timings = timing::Timings::start();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let config_path = args.config.clone().or_else(|| {
+        let default = std::path::PathBuf::from(config_file::DEFAULT_CONFIG_PATH);
+        default.exists().then_some(default)
+    });
+    let file: ConfigFile = config_path
+        .map(|path| ConfigFile::load(&path))
+        .transpose()
+        .and_then(|file| {
+            let file = file.unwrap_or_default();
+            file.apply(&mut args, &matches)?;
+            Ok(file)
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        });
+    let bookmarks_path = std::path::Path::new(bookmarks::BOOKMARKS_PATH);
+    /* Only read when asked for, so a broken file doesn't stop other renders */
+    let load_bookmarks = || -> bookmarks::Bookmarks {
+        bookmarks::load(bookmarks_path).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        })
+    };
+    if let Some(name) = &args.bookmark {
+        let Some(viewport) = load_bookmarks().get(name).copied() else {
+            eprintln!("error: no bookmark named '{name}'");
+            std::process::exit(2);
+        };
+        args.center = Some(viewport.center);
+        args.zoom = Some(viewport.zoom);
+        args.rotation = Some(viewport.rotation);
+    }
+    let width: i32 = args.width.or(file.width).unwrap_or(3840);
+    let height: i32 = args.height.or(file.height).unwrap_or(2160);
+    let threshold: f64 = args.threshold;
+    if args.smooth && threshold < SMOOTH_MIN_THRESHOLD {
+        eprintln!(
+            "warning: smooth coloring with --threshold {threshold} may band; \
+             try --threshold 1000"
+        );
+    }
+    if !args.rays.is_empty() && args.formula != Formula::Mandelbrot {
+        eprintln!("error: --rays only traces rays of the Mandelbrot formula");
+        std::process::exit(2);
+    }
+    let (render_width, render_height) =
+        match (width.checked_mul(args.ssaa), height.checked_mul(args.ssaa)) {
+            (Some(w), Some(h)) => (w, h),
+            _ => {
+                eprintln!(
+                    "error: --ssaa {} makes {width}x{height} too large",
+                    args.ssaa
+                );
+                std::process::exit(2);
+            }
+        };
+    if render_width as i64 * render_height as i64 > SSAA_WARN_PIXELS {
+        eprintln!(
+            "warning: --ssaa {} renders {render_width}x{render_height}, about {} MiB per \
+             buffer before downscaling",
+            args.ssaa,
+            render_width as i64 * render_height as i64 * 3 / (1024 * 1024)
+        );
+    }
+    let config = render_config(&args, render_width, render_height).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(2);
+    });
+    if let Some(deep) = &config.deep {
+        eprintln!("deep zoom: {} bits of fixed point", deep.bits());
+        let f64_only: Vec<&str> = [
+            (args.mode == Mode::Buddhabrot, "--mode buddhabrot"),
+            (args.mode == Mode::Nebulabrot, "--mode nebulabrot"),
+            (args.mode == Mode::AxisEscape, "--mode axis-escape"),
+            (
+                args.interior_coloring == interior::Coloring::Period,
+                "--interior-coloring period",
+            ),
+            (args.flag_undecided.is_some(), "--flag-undecided"),
+            (args.orbit.is_some(), "--orbit"),
+            (!args.rays.is_empty(), "--rays"),
+        ]
+        .into_iter()
+        .filter_map(|(used, option)| used.then_some(option))
+        .collect();
+        if !f64_only.is_empty() {
+            eprintln!(
+                "warning: {} only run in f64, which is too coarse for this view",
+                f64_only.join(", ")
+            );
+        }
+    } else if deep::beyond_f64(&config) {
+        eprintln!(
+            "warning: the view is deeper than f64 resolves, but --formula multibrot \
+             with --exponent {} only iterates in f64",
+            config.exponent
+        );
+    }
+    let svg_levels: Vec<i32> = if args.svg_levels.is_empty() {
+        vec![config.max_iter]
+    } else {
+        args.svg_levels.clone()
+    };
+    if let Some(level) = svg_levels.iter().find(|level| **level > config.max_iter) {
+        eprintln!(
+            "error: --svg-levels {level} is above --max-iter {}",
+            config.max_iter
+        );
+        std::process::exit(2);
+    }
+    if let Some([re_min, re_max, im_min, im_max]) = args.bounds {
+        let box_aspect: f64 = (re_max - re_min) / (im_max - im_min);
+        let image_aspect: f64 = width as f64 / height as f64;
+        if (box_aspect / image_aspect - 1.0).abs() > BOUNDS_ASPECT_TOLERANCE {
+            eprintln!(
+                "warning: --bounds is {box_aspect:.3}:1 but the image is {image_aspect:.3}:1, \
+                 so it shows more of the plane than asked"
+            );
+        }
     }
 
-    fn get_color(&self, k: NotNan<f64>) -> Rgb<u8> {
-        let mut prev_key: &NotNan<f64> = self._keys.first().unwrap();
+    if let Some(Command::Inspect {
+        x,
+        y,
+        width,
+        height,
+        escape,
+    }) = args.command
+    {
+        /* f64 Display is the shortest string that parses back to the same value */
+        let inspect_config = RenderConfig {
+            width,
+            height,
+            ..config.clone()
+        };
+        if let Err(e) = inspect_config.validate() {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+        let c = inspect_config.pixel_to_complex(x, y);
+        let point = inspect_config.plane_point(c);
+        println!("{}{:+}i", point.re, point.im);
+        if escape {
+            let result = config.escape(c);
+            let z = result.final_z;
+            println!(
+                "escaped={} count={} z={}{:+}i",
+                !result.in_set, result.count, z.re, z.im
+            );
+        }
+        return;
+    }
 
-        if k <= *prev_key {
-            return *self._key_map.get(prev_key).unwrap();
+    if let Some(Command::Palettes { sheet }) = &args.command {
+        let palettes: Vec<(String, Palette)> = PaletteName::value_variants()
+            .iter()
+            .map(|name: &PaletteName| {
+                let value = name.to_possible_value().unwrap();
+                println!("{:<10} {}", value.get_name(), value.get_help().unwrap());
+                let (palette, _) = build_palette(*name, false);
+                (value.get_name().to_string(), palette)
+            })
+            .collect();
+        if let Some(sheet_path) = sheet {
+            save_image(
+                &sheet::contact_sheet(&palettes).into(),
+                sheet_path,
+                None,
+                false,
+            )
+            .unwrap();
         }
+        return;
+    }
 
-        for cur_key in &self._keys {
-            if cur_key >= &k {
-                let prev_color: &Rgb<u8> = self._key_map.get(prev_key).unwrap();
-                let cur_color: &Rgb<u8> = self._key_map.get(cur_key).unwrap();
-                let interpolation_factor: NotNan<f64> = (k - prev_key) / (cur_key - prev_key);
-                let prev_color_mathy: MathyColor<f64> = MathyColor::from_ref(prev_color);
-                let cur_color_mathy: MathyColor<f64> = MathyColor::from_ref(cur_color);
-                return prev_color_mathy
-                    .lerp(cur_color_mathy, *interpolation_factor)
-                    .unwrap();
+    if let Some(Command::Bookmark { action }) = &args.command {
+        let mut saved = load_bookmarks();
+        match action {
+            BookmarkAction::Add { name } => {
+                let Some(viewport) = config.viewport else {
+                    eprintln!(
+                        "error: nothing to save; frame a view with --center, --zoom or --location"
+                    );
+                    std::process::exit(2);
+                };
+                saved.insert(name.clone(), viewport);
+            }
+            BookmarkAction::Remove { name } => {
+                if saved.remove(name).is_none() {
+                    eprintln!("error: no bookmark named '{name}'");
+                    std::process::exit(2);
+                }
+            }
+            BookmarkAction::List => {
+                for (name, viewport) in &saved {
+                    println!("{name:<20} {}", viewport.cli_args());
+                }
+                return;
             }
-            prev_key = cur_key;
         }
-        panic!()
+        bookmarks::save(bookmarks_path, &saved).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        });
+        return;
     }
-}
-fn main() {
-    let width: i32 = 3840;
-    let height: i32 = 2160;
-    let threshold: f64 = 2.0;
-    let velocities: Vec<Vec<i32>> = get_divergence_vel(width, height, threshold);
-    let mut palette: Palette = Palette::new();
 
-    let cols: Vec<Rgb<u8>> = vec![
-        Rgb([229, 208, 204]),
-        Rgb([229, 208, 204]),
-        Rgb([23, 33, 33]),
-    ];
+    if let Some(Command::Area { samples, seed }) = args.command {
+        let estimate = area::estimate(&config, samples, seed);
+        println!(
+            "area {:.6} +/- {:.6} (95% confidence, {} of {} samples in the set)",
+            estimate.area, estimate.error, estimate.inside, estimate.samples
+        );
+        return;
+    }
 
-    let col_keys: Vec<NotNan<f64>> = vec![
-        NotNan::try_from(0.0).unwrap(),
-        NotNan::try_from(0.15).unwrap(),
-        NotNan::try_from(1.0).unwrap(),
-    ];
+    if let Some(Command::Bench { runs, budget }) = args.command {
+        let report = bench::run(&config, runs);
+        let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+        println!(
+            "{}x{} in {:.1} ms best, {:.1} ms median of {} runs: {:.2} Mpixels/s",
+            bench::SCENE_WIDTH,
+            bench::SCENE_HEIGHT,
+            ms(report.best()),
+            ms(report.median()),
+            runs,
+            report.throughput() / 1e6
+        );
+        if let Some(budget) = budget {
+            if report.throughput() / 1e6 < budget {
+                eprintln!("error: below the budget of {budget} Mpixels/s");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if let Some(Command::FindTarget { steps }) = args.command {
+        let path = target::find(&config, steps);
+        for (i, viewport) in path.iter().enumerate() {
+            let c = viewport.center;
+            println!(
+                "step {}: center {}{:+}i zoom {}",
+                i + 1,
+                c.re,
+                c.im,
+                viewport.zoom
+            );
+        }
+        match path.last() {
+            Some(viewport) => println!("{}", viewport.cli_args()),
+            None => eprintln!("no boundary in view to follow"),
+        }
+        return;
+    }
 
-    for (k, v) in std::iter::zip(col_keys.into_iter(), cols.into_iter()) {
-        palette.add_col(k, &v);
+    let (mut palette, interior): (Palette, Rgb<u8>) = match (&args.palette_file, &args.gradient) {
+        (Some(palette_path), _) => {
+            let palette = Palette::from_ggr(palette_path).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            });
+            let interior = palette.get_color(NotNan::new(1.0).unwrap());
+            (palette, interior)
+        }
+        (None, Some(gradient)) => {
+            let interior = gradient.get_color(NotNan::new(1.0).unwrap());
+            (gradient.clone(), interior)
+        }
+        (None, None) => build_palette(args.palette, args.cycle_frames.is_some()),
+    };
+    palette.set_blend_space(args.blend_space);
+    if !args.palette_easing.is_empty() {
+        if let Err(e) = palette.ease_segments(&args.palette_easing) {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        }
+    }
+
+    let path = args
+        .out
+        .clone()
+        .or_else(|| file.output.clone())
+        .unwrap_or_else(|| format!("mandelbrot{width}x{height}.png"));
+    let format: Option<ImageFormat> = args.out_format.map(OutFormat::image_format);
+    /* EXR holds only floats, and no other format holds them */
+    let exr = format.or_else(|| ImageFormat::from_path(&path).ok()) == Some(ImageFormat::OpenExr);
+    if exr != args.pixel_format.is_float() {
+        eprintln!("error: EXR output and the rgb32f/rgba32f pixel formats only go together");
+        std::process::exit(2);
+    }
+    let animated = args.cycle_frames.is_some() || args.julia_path.is_some();
+    if path == STDOUT_PATH && animated && args.cycle_format == cycle::Format::Png {
+        eprintln!("error: PNG frames can't be written to stdout, use --cycle-format gif");
+        std::process::exit(2);
+    }
+    if path == STDOUT_PATH && args.stats_json.as_deref() == Some(STDOUT_PATH) {
+        eprintln!("error: the image and --stats-json can't both go to stdout");
+        std::process::exit(2);
+    }
+    if args.row_timing.is_some() && args.order != Order::RowMajor {
+        eprintln!("error: --row-timing needs --order row-major, other orders don't compute rows");
+        std::process::exit(2);
+    }
+
+    if args.dry_run {
+        print_dry_run(&config, &args, &path);
+        return;
+    }
+
+    /* The settings for one side of compare and diff */
+    let side = |path: &Option<std::path::PathBuf>, name: Option<PaletteName>| {
+        let mut side_args: Args = args.clone();
+        if let Some(path) = path {
+            ConfigFile::load(path)
+                .and_then(|file| file.apply(&mut side_args, &matches))
+                .unwrap_or_else(|e| {
+                    eprintln!("error: {e}");
+                    std::process::exit(2);
+                });
+        }
+        let (palette, _) = build_palette(name.unwrap_or(side_args.palette), false);
+        let config = render_config(&side_args, width, height).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        });
+        (config, palette)
+    };
+
+    if let Some(Command::Compare {
+        left,
+        right,
+        left_palette,
+        right_palette,
+        divider,
+    }) = &args.command
+    {
+        let (left_config, left_palette) = side(left, *left_palette);
+        let (right_config, right_palette) = side(right, *right_palette);
+        let imgbuf = compare::render(
+            [
+                (&left_config, &left_palette),
+                (&right_config, &right_palette),
+            ],
+            *divider,
+        );
+        save_image(
+            &args.pixel_format.convert(imgbuf, None),
+            &path,
+            format,
+            args.indexed,
+        )
+        .unwrap();
+        return;
+    }
+
+    if let Some(Command::Diff {
+        left,
+        right,
+        left_palette,
+        right_palette,
+        amplify,
+    }) = &args.command
+    {
+        let (left_config, left_palette) = side(left, *left_palette);
+        let (right_config, right_palette) = side(right, *right_palette);
+        let (imgbuf, summary) = diff::render(
+            [
+                (&left_config, &left_palette),
+                (&right_config, &right_palette),
+            ],
+            *amplify,
+        );
+        println!(
+            "max difference {:.1}, mean {:.4}, {} of {} pixels changed",
+            summary.max,
+            summary.mean,
+            summary.changed,
+            width as i64 * height as i64
+        );
+        save_image(
+            &args.pixel_format.convert(imgbuf, None),
+            &path,
+            format,
+            args.indexed,
+        )
+        .unwrap();
+        return;
+    }
+
+    #[cfg(feature = "server")]
+    if let Some(Command::Serve { port, live_port }) = args.command {
+        mandelbrot::server::run(&config, &palette, port, live_port);
+        return;
+    }
+
+    if !file.layers.is_empty() {
+        let stack: Vec<layers::Layer> = file
+            .layers
+            .iter()
+            .map(
+                |spec: &config_file::LayerSpec| -> Result<layers::Layer, String> {
+                    let mut layer_args: Args = args.clone();
+                    spec.apply(&mut layer_args)?;
+                    let config = render_config(&layer_args, render_width, render_height)?;
+                    Ok(layers::Layer {
+                        config,
+                        palette: build_palette(layer_args.palette, false).0,
+                        opacity: spec.opacity.unwrap_or(1.0),
+                        transparent_interior: spec.transparent_interior,
+                    })
+                },
+            )
+            .collect::<Result<_, _>>()
+            .unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            });
+        let image = DynamicImage::ImageRgba8(layers::composite(&stack));
+        save_image(&downscale(image, args.ssaa), &path, format, false).unwrap();
+        return;
+    }
+
+    if args.ascii {
+        let (columns, lines) = ascii::terminal_size();
+        /* Two pixel rows per line, leaving the last line for the prompt */
+        let ascii_config =
+            render_config(&args, columns, 2 * (lines - 1).max(1)).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            });
+        print!(
+            "{}",
+            ascii::render(&get_divergence_vel(&ascii_config), ascii_config.max_iter)
+        );
+        return;
+    }
+
+    let sampling = buddhabrot::Sampling {
+        samples: args.samples,
+        min_iter: args.buddhabrot_min_iter,
+        importance: args.importance_sampling,
+        seed: args.seed,
+    };
+    match args.mode {
+        Mode::Escape | Mode::BinaryDecomposition | Mode::Spiral => {}
+        Mode::AxisEscape => {
+            /* Fast axes come out bright, like the default palette's fast escapes */
+            let channel = |count: i32| -> u8 {
+                ((1.0 - count as f64 / config.max_iter as f64) * u8::MAX as f64).round() as u8
+            };
+            let counts: Vec<(i32, i32)> =
+                map_rows(0..config.height, &config, |c: C64| config.axis_escape(c))
+                    .into_iter()
+                    .flatten()
+                    .collect();
+            let imgbuf: ImageBuffer<Rgb<u8>, Vec<u8>> =
+                ImageBuffer::from_fn(config.width as u32, config.height as u32, |x, y| {
+                    let (re, im) = counts[(y * config.width as u32 + x) as usize];
+                    Rgb([channel(re), channel(im), 0])
+                });
+            let image = args.pixel_format.convert(imgbuf, None);
+            save_image(&downscale(image, args.ssaa), &path, format, args.indexed).unwrap();
+            return;
+        }
+        Mode::Buddhabrot => {
+            let imgbuf = buddhabrot::render_buddhabrot(&config, &sampling, args.buddhabrot_iter);
+            let image = args.pixel_format.convert(imgbuf, None);
+            save_image(&downscale(image, args.ssaa), &path, format, args.indexed).unwrap();
+            return;
+        }
+        Mode::Nebulabrot => {
+            let imgbuf = buddhabrot::render_nebulabrot(&config, &sampling, args.nebula_iters);
+            let image = args.pixel_format.convert(imgbuf, None);
+            save_image(&downscale(image, args.ssaa), &path, format, args.indexed).unwrap();
+            return;
+        }
+    }
+
+    if let Some(frames) = args.cycle_frames {
+        let velocities = get_divergence_vel(&config);
+        cycle::save(
+            &path,
+            &velocities,
+            &config,
+            &palette,
+            interior,
+            frames,
+            args.cycle_format,
+        )
+        .unwrap();
+        return;
+    }
+
+    if let Some(julia_path) = &args.julia_path {
+        let images = (0..args.julia_frames).map(|i| {
+            let frame_config = RenderConfig {
+                julia: Some(julia_path.at(i, args.julia_frames)),
+                ..config.clone()
+            };
+            /* Colored as a still render of the frame would be */
+            let velocities = get_divergence_vel(&frame_config);
+            let count_lut = count_colors(&args, &frame_config, &palette, Some(&velocities));
+            let width: usize = frame_config.width as usize;
+            let mut imgbuf: ImageBuffer<Rgb<u8>, Vec<u8>> =
+                ImageBuffer::new(frame_config.width as u32, frame_config.height as u32);
+            fill_pixels(&mut imgbuf, &frame_config, |i: usize| {
+                count_lut.count(velocities[i / width][i % width]).unwrap()
+            });
+            draw_overlays(
+                &args,
+                &frame_config,
+                &mut imgbuf,
+                Some(&velocities),
+                &mut timing::Timings::start(),
+            );
+            imgbuf
+        });
+        cycle::write_frames(&path, images, args.cycle_format).unwrap();
+        return;
+    }
+
+    if args.stream {
+        render_streaming(&path, &config, &palette).unwrap();
+        return;
     }
 
     let mut imgbuf: ImageBuffer<_, Vec<_>> =
-        ImageBuffer::new(width.try_into().unwrap(), height.try_into().unwrap());
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let velocity: i32 = velocities[y as usize][x as usize];
-        *pixel = gradient(velocity, &palette);
+        ImageBuffer::new(config.width as u32, config.height as u32);
+
+    if let Some(tile_size) = args.tile_size {
+        /* Tiles come back downscaled, so the buffer has the output size */
+        let imgbuf = std::sync::Mutex::new(ImageBuffer::new(width as u32, height as u32));
+        let place = |tile: tiles::Tile| {
+            let mut imgbuf = imgbuf.lock().unwrap();
+            for ty in 0..tile.height {
+                for tx in 0..tile.width {
+                    let pixel = tile.pixels[(ty * tile.width + tx) as usize];
+                    imgbuf.put_pixel((tile.x + tx) as u32, (tile.y + ty) as u32, pixel);
+                }
+            }
+        };
+        if args.ssaa > 1 {
+            tiles::render_tiles_downscaled(
+                &config,
+                &palette,
+                tile_size,
+                args.ssaa,
+                args.tile_apron,
+                place,
+            );
+        } else {
+            tiles::render_tiles(&config, &palette, tile_size, place);
+        }
+        let image = args
+            .pixel_format
+            .convert(imgbuf.into_inner().unwrap(), None);
+        save_image(&image, &path, format, args.indexed).unwrap();
+        return;
+    }
+
+    let needs_velocities: bool = (args.supersample <= 1
+        && args.color_expr.is_none()
+        && !args.smooth
+        && !args.coverage_aa
+        && args.log_map.is_none())
+        || args.escape_map.is_some()
+        || args.svg.is_some()
+        || args.flag_glitches.is_some()
+        || args.flag_undecided.is_some()
+        || args.interior_coloring == interior::Coloring::Period
+        || args.stats_json.is_some()
+        || args.row_timing.is_some();
+    let refine_min_count: i32 = args.refine_min_count.unwrap_or(config.max_iter);
+    let prior: Option<Vec<Vec<i32>>> = args.refine_from.as_ref().map(|prior_path| {
+        load_escape_map(prior_path, &config).unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            std::process::exit(2);
+        })
+    });
+    timings.lap("setup");
+    /* Filled in by the escape pass itself when --row-timing asks for it */
+    let mut row_times: Vec<std::time::Duration> = Vec::new();
+    let velocities: Option<Vec<Vec<i32>>> =
+        needs_velocities.then(|| match (&prior, &args.checkpoint) {
+            (Some(prior), _) => {
+                let mask: Vec<Vec<bool>> = prior
+                    .iter()
+                    .map(|row| row.iter().map(|v| *v >= refine_min_count).collect())
+                    .collect();
+                get_divergence_vel_masked(&config, prior, &mask)
+            }
+            (None, Some(checkpoint_path)) => {
+                checkpoint::divergence_vel(&config, checkpoint_path, args.checkpoint_rows).unwrap()
+            }
+            (None, None) if args.row_timing.is_some() => get_divergence_vel_timed(&config)
+                .into_iter()
+                .map(|(row, time)| {
+                    row_times.push(time);
+                    row
+                })
+                .collect(),
+            (None, None) => get_divergence_vel(&config),
+        });
+    if needs_velocities {
+        timings.lap("escape");
+    }
+
+    /* Integer counts index this directly; continuous positions only use --lut-size */
+    let count_lut = count_colors(&args, &config, &palette, velocities.as_ref());
+    let fine_lut: Option<lut::ColorLut> = args
+        .lut_size
+        .map(|entries: u32| lut::ColorLut::fine(&palette, entries as usize));
+    /* Unquantized colors for formats wider than the 8-bit buffer */
+    let mut fine: Option<Vec<MathyColor<f64>>> = None;
+    let float_path: bool = args.mode == Mode::BinaryDecomposition
+        || args.mode == Mode::Spiral
+        || args.supersample > 1
+        || args.bloom.is_some()
+        || args.channel_gamma.is_some()
+        || args.color_expr.is_some()
+        || args.smooth
+        || args.coverage_aa
+        || args.log_map.is_some();
+    if float_path {
+        /* Keep colors unquantized until every float-space pass has run */
+        let transfer: Option<Transfer> = match args.blend_space {
+            BlendSpace::Lab => Some(Transfer::Lab),
+            BlendSpace::Rgb => args.linear_aa.then_some(args.gamma),
+        };
+        let mut colors: Vec<MathyColor<f64>> = if args.mode == Mode::BinaryDecomposition {
+            map_rows(0..config.height, &config, |c: C64| {
+                let result = config.escape(c);
+                (
+                    config.position_of(&result),
+                    !result.in_set && result.final_z.im <= 0.0,
+                )
+            })
+            .into_iter()
+            .flatten()
+            .map(|(pos, lower): (f64, bool)| {
+                let color = palette.get_mathy_color(NotNan::try_from(pos).unwrap());
+                if lower {
+                    color * BINARY_DECOMPOSITION_SHADE
+                } else {
+                    color
+                }
+            })
+            .collect()
+        } else if args.mode == Mode::Spiral {
+            map_rows(0..config.height, &config, |c: C64| {
+                let result = config.escape(c);
+                if result.in_set {
+                    return config.position_of(&result);
+                }
+                /* The escaped z's angle in turns, so each band sweeps once around */
+                let turn: f64 = (result.final_z.arg() / std::f64::consts::TAU).rem_euclid(1.0);
+                config.count_position(result.count as f64 + turn)
+            })
+            .into_iter()
+            .flatten()
+            .map(|pos: f64| palette.get_mathy_color(NotNan::try_from(pos).unwrap()))
+            .collect()
+        } else if args.supersample > 1 && args.de_adaptive {
+            let pixel = supersampler(
+                &config,
+                args.supersample,
+                args.subsample_pattern,
+                args.aa_filter,
+                &palette,
+                transfer,
+            );
+            distance::adaptive_colors(&config, &palette, pixel)
+        } else if args.supersample > 1 {
+            get_supersampled_colors(
+                &config,
+                args.supersample,
+                args.subsample_pattern,
+                args.aa_filter,
+                &palette,
+                transfer,
+            )
+        } else if args.coverage_aa {
+            distance::coverage_colors(&config, &palette)
+        } else if (config.smooth || config.log_base.is_some()) && args.color_expr.is_none() {
+            map_rows(0..config.height, &config, |c: C64| {
+                config.escape_position(c)
+            })
+            .into_iter()
+            .flatten()
+            .map(|pos: f64| match &fine_lut {
+                Some(lut) => lut.position(pos),
+                None => palette.get_mathy_color(NotNan::try_from(pos).unwrap()),
+            })
+            .collect()
+        } else if let Some(expr) = &args.color_expr {
+            map_rows(0..config.height, &config, |c: C64| config.escape(c))
+                .into_iter()
+                .flatten()
+                .map(|result: EscapeResult| {
+                    let t: f64 = result.count as f64 / config.max_iter as f64;
+                    let pos = expr.position(t, result.final_z.norm(), result.count);
+                    palette.get_mathy_color(NotNan::try_from(pos).unwrap())
+                })
+                .collect()
+        } else {
+            velocities
+                .iter()
+                .flatten()
+                .flatten()
+                .map(|velocity: &i32| count_lut.count(*velocity))
+                .collect()
+        };
+        /* Unless counts were needed up front, these passes escape and color in one go */
+        timings.lap(if needs_velocities {
+            "coloring"
+        } else {
+            "escape + coloring"
+        });
+        if let Some(intensity) = args.bloom {
+            bloom::apply(
+                &mut colors,
+                config.width as usize,
+                config.height as usize,
+                intensity,
+            );
+            timings.lap("bloom");
+        }
+        if let Some(gammas) = args.channel_gamma {
+            let exponents: [f64; 3] = gammas.map(f64::recip);
+            let grade = |color: &mut MathyColor<f64>| *color = color.channel_curves(exponents);
+            if config.single_threaded {
+                colors.iter_mut().for_each(grade);
+            } else {
+                colors.par_iter_mut().for_each(grade);
+            }
+            timings.lap("grading");
+        }
+        fill_pixels(&mut imgbuf, &config, |i: usize| colors[i].unwrap());
+        if args.pixel_format.is_wide() {
+            fine = Some(colors);
+        }
+    } else {
+        let velocities: &Vec<Vec<i32>> = velocities.as_ref().unwrap();
+        let width: usize = config.width as usize;
+        fill_pixels(&mut imgbuf, &config, |i: usize| {
+            count_lut.count(velocities[i / width][i % width]).unwrap()
+        });
+        if args.pixel_format.is_wide() {
+            fine = Some(
+                velocities
+                    .iter()
+                    .flatten()
+                    .map(|velocity: &i32| count_lut.count(*velocity))
+                    .collect(),
+            );
+        }
+        timings.lap("coloring");
     }
 
-    imgbuf
-        .save(format!("mandelbrot{width}x{height}.png"))
+    draw_overlays(
+        &args,
+        &config,
+        &mut imgbuf,
+        velocities.as_ref(),
+        &mut timings,
+    );
+
+    if let Some(escape_path) = &args.escape_map {
+        save_escape_map(
+            escape_path,
+            velocities.as_ref().unwrap(),
+            config.max_iter,
+            args.escape_map_16bit,
+            args.escape_map_interior,
+        )
         .unwrap();
+    }
+
+    if let Some(svg_path) = &args.svg {
+        let layers: Vec<(i32, Rgb<u8>)> = svg_levels
+            .iter()
+            .map(|level: &i32| (*level, gradient((level - 1) as f64, &config, &palette)))
+            .collect();
+        contour::save_svg(svg_path, velocities.as_ref().unwrap(), &layers).unwrap();
+    }
+    if let Some(height_path) = &args.height_map {
+        save_height_map(height_path, &config, args.height_scale).unwrap();
+    }
+    if let Some(work_path) = &args.work_map {
+        save_work_map(work_path, &config).unwrap();
+    }
+    if let Some(timing_path) = &args.row_timing {
+        save_row_timing(timing_path, &row_times).unwrap();
+    }
+    if let Some(mask_path) = &args.mask {
+        render_mask(width, height, &config).save(mask_path).unwrap();
+    }
+    if args.escape_map.is_some()
+        || args.svg.is_some()
+        || args.height_map.is_some()
+        || args.work_map.is_some()
+        || args.row_timing.is_some()
+        || args.mask.is_some()
+    {
+        timings.lap("extra maps");
+    }
+
+    let image = downscale(
+        args.pixel_format.convert(imgbuf, fine.as_deref()),
+        args.ssaa,
+    );
+    if args.ssaa > 1 {
+        timings.lap("downscale");
+    }
+    save_image(&image, &path, format, args.indexed).unwrap();
+    timings.lap("write");
+    if args.timing {
+        timings.print();
+    }
+    if let Some(stats_path) = &args.stats_json {
+        stats::RenderStats::new(&config, velocities.as_ref().unwrap(), timings.phases())
+            .write(stats_path)
+            .unwrap();
+    }
 }
 
-fn transform(base: C64) -> C64 {
-    let base = (base + C64::new(0.0, -0.53)) * C64::i();
-    if base.abs() == 0.0 {
-        C64::new(10.0, 10.0)
-    } else {
-        0.4 / base
+/* The passes a still render makes over its colored counts: period interior
+colors, the glitch and undecided flags, then the orbit and ray overlays */
+fn draw_overlays(
+    args: &Args,
+    config: &RenderConfig,
+    imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    velocities: Option<&Vec<Vec<i32>>>,
+    timings: &mut timing::Timings,
+) {
+    if args.interior_coloring == interior::Coloring::Period {
+        let periods = interior::period_colors(config, velocities.unwrap());
+        for (pixel, color) in imgbuf.pixels_mut().zip(periods) {
+            if let Some(color) = color {
+                *pixel = color;
+            }
+        }
+        timings.lap("interior");
+    }
+
+    if let Some(glitch_color) = args.flag_glitches {
+        let suspects = glitch::suspect_pixels(config, velocities.unwrap());
+        for (pixel, suspect) in imgbuf.pixels_mut().zip(suspects) {
+            if suspect {
+                *pixel = glitch_color;
+            }
+        }
+    }
+
+    if let Some(undecided_color) = args.flag_undecided {
+        let undecided = interior::undecided_pixels(config, velocities.unwrap());
+        for (pixel, undecided) in imgbuf.pixels_mut().zip(undecided) {
+            if undecided {
+                *pixel = undecided_color;
+            }
+        }
+    }
+
+    if let Some(point) = args.orbit {
+        overlay::draw_orbit(imgbuf, config, point, args.orbit_color);
+    }
+    if !args.rays.is_empty() {
+        rays::draw_rays(imgbuf, config, &args.rays, args.ray_color);
+    }
+    if args.flag_glitches.is_some()
+        || args.flag_undecided.is_some()
+        || args.orbit.is_some()
+        || !args.rays.is_empty()
+    {
+        timings.lap("overlays");
     }
 }
 
-fn get_divergence_vel(width: i32, height: i32, threshold: f64) -> Vec<Vec<i32>> {
-    let aspect_ratio: Rational64 = Rational64::new(width as i64, height as i64);
-    let y_scale: Rational64 = Rational64::new(112, 100);
-    let x_scale: Rational64 = y_scale * aspect_ratio;
+/* A color per escape count as a still render places them, equalized over
+`velocities` with --histogram */
+fn count_colors(
+    args: &Args,
+    config: &RenderConfig,
+    palette: &Palette,
+    velocities: Option<&Vec<Vec<i32>>>,
+) -> lut::ColorLut {
+    match velocities {
+        Some(velocities) if args.histogram => lut::ColorLut::from_positions(
+            palette,
+            &histogram::equalized_positions(velocities, config.max_iter),
+        ),
+        _ => lut::ColorLut::counts(palette, config),
+    }
+}
 
-    let grid: Vec<_> = (0..height)
-        .map(|h: i32| -> Vec<_> {
-            let y = Rational64::new(2 * h as i64, height as i64) * y_scale - y_scale;
-            (0..width)
-                .map(|w: i32| -> (Rational64, Rational64) {
-                    let x = Rational64::new(2 * w as i64, width as i64) * x_scale - x_scale;
-                    (x, y)
-                })
-                .collect()
-        })
+/* The config for `args`, validated before any fixed point is set up for it */
+fn render_config(args: &Args, width: i32, height: i32) -> Result<RenderConfig, String> {
+    RenderConfig {
+        width,
+        height,
+        threshold: args.threshold,
+        formula: args.formula,
+        exponent: args.exponent,
+        viewport: resolve_viewport(args, width, height),
+        julia: args.julia,
+        z0: args.z0,
+        chunk_rows: args.chunk_rows,
+        chunk_pixels: args.chunk_pixels,
+        smooth: args.smooth,
+        min_count: args.min_count,
+        bias: args.bias,
+        log_base: args.log_map,
+        color_offset: args.color_offset,
+        max_iter: args.max_iter,
+        interior_max_iter: args.interior_max_iter.unwrap_or(args.max_iter),
+        order: args.order,
+        coordinates: args.coordinates,
+        single_threaded: args.single_threaded || cfg!(target_arch = "wasm32"),
+        half_height: args.half_height,
+        deep: None,
+    }
+    .with_decimal_center(args.center_re.as_deref(), args.center_im.as_deref())
+}
+
+/// Writes `round(clamp(scale * nu / max_iter, 0, 1) * 65535)` per pixel, where
+/// nu is the normalized iteration count used by smooth coloring (computed
+/// with the render's threshold and SMOOTH_EXTRA_ITERATIONS). Points that never
+/// escape have nu = max_iter, so at scale 1.0 they are the highest level.
+fn save_height_map(path: &str, config: &RenderConfig, scale: f64) -> image::ImageResult<()> {
+    /* The raw count, so the palette settings (min_count, bias, color_offset,
+    log_base) don't bend the heights */
+    let smooth_config = RenderConfig {
+        smooth: true,
+        ..config.clone()
+    };
+    let max_iter = config.max_iter as f64;
+    let levels: Vec<u16> = map_rows(0..config.height, &smooth_config, |c: C64| {
+        let nu: f64 = smooth_config.escape(c).smooth;
+        ((scale * nu / max_iter).clamp(0.0, 1.0) * u16::MAX as f64).round() as u16
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    let imgbuf: ImageBuffer<Luma<u16>, Vec<u16>> =
+        ImageBuffer::from_raw(config.width as u32, config.height as u32, levels).unwrap();
+    imgbuf.save(path)
+}
+
+/// Writes a CSV of row index and microseconds from the times
+/// `get_divergence_vel_timed` took for each row of the render itself, so they
+/// show the schedule the render really ran, rows overlapping on the threads.
+fn save_row_timing(path: &str, row_times: &[std::time::Duration]) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "row,microseconds")?;
+    for (h, time) in row_times.iter().enumerate() {
+        writeln!(out, "{h},{}", time.as_micros())?;
+    }
+    out.flush()
+}
+
+/// Writes the iterations spent on each pixel as 8-bit grayscale, scaled so
+/// the most expensive pixel is white. Unlike the escape map this counts the
+/// work done, so it shows where a render spends its time.
+fn save_work_map(path: &str, config: &RenderConfig) -> image::ImageResult<()> {
+    let work: Vec<i32> = map_rows(0..config.height, config, |c: C64| {
+        config.iterations_performed(c)
+    })
+    .into_iter()
+    .flatten()
+    .collect();
+    let max: f64 = work.iter().copied().max().unwrap_or(0).max(1) as f64;
+    let levels: Vec<u8> = work
+        .iter()
+        .map(|w: &i32| (*w as f64 / max * u8::MAX as f64).round() as u8)
         .collect();
+    let imgbuf: ImageBuffer<Luma<u8>, Vec<u8>> =
+        ImageBuffer::from_raw(config.width as u32, config.height as u32, levels).unwrap();
+    imgbuf.save(path)
+}
 
-    grid.into_par_iter()
-        .map(|row| -> Vec<i32> {
-            row.into_par_iter()
-                .map(|c: (Ratio<i64>, Ratio<i64>)| -> i32 {
-                    let (re, im) = c;
-                    let re: f64 = re.to_f64().expect("Couldn't cast to float.");
-                    let im: f64 = im.to_f64().expect("Couldn't cast to float");
-                    let c = transform(C64::new(re, im));
-                    diverges_in(c, threshold)
-                })
-                .collect()
-        })
-        .collect()
+fn resolve_viewport(args: &Args, width: i32, height: i32) -> Option<Viewport> {
+    if let Some([re_min, re_max, im_min, im_max]) = args.bounds {
+        let viewport = Viewport::from_bounds(
+            C64::new(re_min, im_min),
+            C64::new(re_max, im_max),
+            width as f64 / height as f64,
+            args.half_height.to_f64().unwrap(),
+        );
+        return match viewport {
+            Ok(viewport) => Some(viewport),
+            Err(e) => {
+                eprintln!("error: {e}");
+                std::process::exit(2);
+            }
+        };
+    }
+    if args.auto_frame {
+        let [(re_min, im_min), (re_max, im_max)] = SET_BOUNDS;
+        let margin = C64::new(re_max - re_min, im_max - im_min) * AUTO_FRAME_MARGIN;
+        return Some(Viewport::fit(
+            C64::new(re_min, im_min) - margin,
+            C64::new(re_max, im_max) + margin,
+            width as f64 / height as f64,
+            args.half_height.to_f64().unwrap(),
+        ));
+    }
+    if args.location.is_none()
+        && args.center.is_none()
+        && args.center_re.is_none()
+        && args.center_im.is_none()
+        && args.zoom.is_none()
+        && args.rotation.is_none()
+    {
+        return None;
+    }
+    let mut viewport: Viewport = args
+        .location
+        .as_deref()
+        .and_then(Viewport::from_location)
+        .unwrap_or_default();
+    if let Some(center) = args.center {
+        viewport.center = center;
+    }
+    /* The f64 backend rounds to the nearest double */
+    if let Some(re) = &args.center_re {
+        viewport.center.re = re.parse().unwrap();
+    }
+    if let Some(im) = &args.center_im {
+        viewport.center.im = im.parse().unwrap();
+    }
+    if let Some(zoom) = args.zoom {
+        viewport.zoom = zoom;
+    }
+    if let Some(rotation) = args.rotation {
+        viewport.rotation = rotation;
+    }
+    Some(viewport)
 }
 
-fn diverges_in(c: C64, threshold: f64) -> i32 {
-    let mut count: i32 = 0;
-    let mut accumulator: C64 = c;
-    let mut d1: C64 = C64::new(0.0, 0.0);
-    let mut d2: C64;
+/* Points timed to extrapolate the render time in a dry run */
+const DRY_RUN_SAMPLE_GRID: (i32, i32) = (32, 18);
+
+fn print_dry_run(config: &RenderConfig, args: &Args, path: &str) {
+    println!("{config:#?}");
+    println!("mode: {:?}", args.mode);
+    if matches!(args.mode, Mode::Buddhabrot | Mode::Nebulabrot) {
+        /* The master seed every sampling job's seed derives from */
+        println!("seed: {}", args.seed);
+    }
+    if let Some(viewport) = config.viewport {
+        println!("view: {}", viewport.cli_args());
+    }
+    println!("output: {path}");
+
+    let pixels: f64 = config.width as f64 * config.height as f64;
+    let mib = |bytes: f64| bytes / (1024.0 * 1024.0);
+    println!("image memory: {:.1} MiB", mib(pixels * 3.0));
+    /* RGB image, per-pixel i32 counts and the unquantized f64 colors */
+    println!(
+        "peak memory: up to {:.1} MiB",
+        mib(pixels * (3.0 + 4.0 + 24.0))
+    );
+
+    let (sample_w, sample_h) = DRY_RUN_SAMPLE_GRID;
+    let start = std::time::Instant::now();
+    for j in 0..sample_h {
+        for i in 0..sample_w {
+            let w = i * config.width / sample_w;
+            let h = j * config.height / sample_h;
+            std::hint::black_box(config.escape(config.pixel_to_complex(w, h)));
+        }
+    }
+    let per_point: f64 = start.elapsed().as_secs_f64() / (sample_w * sample_h) as f64;
+    let samples: f64 = (args.supersample.max(1) * args.supersample.max(1)) as f64;
+    let estimate: f64 = per_point * pixels * samples / rayon::current_num_threads() as f64;
+    match args.mode {
+        Mode::Escape | Mode::BinaryDecomposition | Mode::Spiral | Mode::AxisEscape => {
+            println!("estimated escape time: {estimate:.1} s")
+        }
+        /* Orbit tracing cost depends on the sample count, not the pixel grid */
+        Mode::Buddhabrot | Mode::Nebulabrot => println!("estimated escape time: n/a"),
+    }
+}
 
-    while accumulator.abs() < threshold && count < ITERMAX {
-        let next_accumulator = next_mandelbrot(accumulator, c);
-        let d = next_accumulator - accumulator;
-        d2 = d1;
-        d1 = d;
-        let second_d = d2 - d1;
-        if second_d.abs() < 0.05 {
-            //return ITERMAX
+fn save_escape_map(
+    path: &str,
+    velocities: &[Vec<i32>],
+    max_iter: i32,
+    sixteen_bit: bool,
+    interior: Interior,
+) -> image::ImageResult<()> {
+    /* Counts are scaled linearly so that max_iter maps to full white */
+    let height: u32 = velocities.len().try_into().unwrap();
+    let width: u32 = velocities[0].len().try_into().unwrap();
+    let level = |x: u32, y: u32, max: f64| -> f64 {
+        let velocity: i32 = velocities[y as usize][x as usize];
+        if velocity >= max_iter && interior == Interior::Black {
+            0.0
+        } else {
+            (velocity as f64 / max_iter as f64 * max).round()
         }
-        accumulator = next_accumulator;
-        count += 1;
+    };
+
+    if sixteen_bit {
+        let imgbuf: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(width, height, |x, y| {
+                Luma([level(x, y, u16::MAX as f64) as u16])
+            });
+        imgbuf.save(path)
+    } else {
+        let imgbuf: ImageBuffer<Luma<u8>, Vec<u8>> = ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([level(x, y, u8::MAX as f64) as u8])
+        });
+        imgbuf.save(path)
     }
+}
 
-    count
+/* Reads back a map `save_escape_map` wrote at either depth, rounding each
+level to the nearest count; a black interior can't be told from count 0 */
+fn load_escape_map(path: &std::path::Path, config: &RenderConfig) -> Result<Vec<Vec<i32>>, String> {
+    let map = image::open(path)
+        .map_err(|e| format!("couldn't read {}: {e}", path.display()))?
+        .into_luma16();
+    if map.dimensions() != (config.width as u32, config.height as u32) {
+        return Err(format!(
+            "{} is {}x{}, the render is {}x{}",
+            path.display(),
+            map.width(),
+            map.height(),
+            config.width,
+            config.height
+        ));
+    }
+    Ok(map
+        .rows()
+        .map(|row| {
+            row.map(|level: &Luma<u16>| {
+                (level.0[0] as f64 / u16::MAX as f64 * config.max_iter as f64).round() as i32
+            })
+            .collect()
+        })
+        .collect())
+}
+
+fn downscale(image: DynamicImage, factor: i32) -> DynamicImage {
+    if factor <= 1 {
+        return image;
+    }
+    let (width, height) = (
+        image.width() / factor as u32,
+        image.height() / factor as u32,
+    );
+    image.resize_exact(width, height, DOWNSCALE_FILTER)
 }
 
-fn next_mandelbrot(z: C64, c: C64) -> C64 {
-    z * z + c
+/// Saves to `path`, or encodes to stdout when `path` is STDOUT_PATH. Without
+/// a `format` the file extension decides, and stdout gets PNG.
+fn save_image(
+    image: &DynamicImage,
+    path: &str,
+    format: Option<ImageFormat>,
+    indexed: bool,
+) -> image::ImageResult<()> {
+    if let (true, Some(imgbuf)) = (indexed, image.as_rgb8()) {
+        match indexed::quantize(imgbuf) {
+            Some((colors, indices)) => {
+                let out: Box<dyn Write> = if path == STDOUT_PATH {
+                    Box::new(std::io::stdout().lock())
+                } else {
+                    Box::new(File::create(path)?)
+                };
+                let (width, height) = imgbuf.dimensions();
+                return indexed::write_png(BufWriter::new(out), width, height, &colors, &indices)
+                    .map_err(|e| {
+                        image::ImageError::Encoding(image::error::EncodingError::new(
+                            ImageFormat::Png.into(),
+                            e,
+                        ))
+                    });
+            }
+            None => eprintln!("warning: more than 256 colors, writing a truecolor PNG instead"),
+        }
+    }
+    if path == STDOUT_PATH {
+        /* Encoders may seek, so the image is built in memory first */
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, format.unwrap_or(ImageFormat::Png))?;
+        std::io::stdout().lock().write_all(bytes.get_ref())?;
+        return Ok(());
+    }
+    match format {
+        Some(format) => image.save_with_format(path, format),
+        None => image.save(path),
+    }
 }
 
-fn gradient(velocity: i32, palette: &Palette) -> Rgb<u8> {
-    let norm: NotNan<f64> = NotNan::try_from(velocity as f64 / ITERMAX as f64).unwrap();
-    palette.get_color(norm)
+fn render_streaming(
+    path: &str,
+    config: &RenderConfig,
+    palette: &Palette,
+) -> Result<(), png::EncodingError> {
+    let (width, height) = (config.width, config.height);
+    /* Only STREAM_BAND_ROWS rows of escape counts and pixels are held at once */
+    let out: Box<dyn Write> = if path == STDOUT_PATH {
+        Box::new(std::io::stdout().lock())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(out),
+        width.try_into().unwrap(),
+        height.try_into().unwrap(),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?.into_stream_writer()?;
+
+    let mut band_start: i32 = 0;
+    while band_start < height {
+        let band_end: i32 = (band_start + STREAM_BAND_ROWS).min(height);
+        let velocities = get_divergence_rows(band_start..band_end, config);
+        let bytes: Vec<u8> = velocities
+            .into_iter()
+            .flatten()
+            .flat_map(|velocity: i32| gradient(velocity as f64, config, palette).0)
+            .collect();
+        writer.write_all(&bytes)?;
+        band_start = band_end;
+    }
+
+    writer.finish()
 }
@@ -0,0 +1,101 @@
+use image::{ImageBuffer, Rgb};
+
+use crate::{orbit_from, RenderConfig, C64};
+
+/// Draws the orbit of `point` as a polyline over `imgbuf`, one segment per
+/// iteration. Segments are clipped to the image, so orbits that leave the view
+/// and come back stay connected.
+pub fn draw_orbit(
+    imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    config: &RenderConfig,
+    point: C64,
+    color: Rgb<u8>,
+) {
+    let (z0, c) = config.orbit_start(point);
+    let points = orbit_from(
+        z0,
+        c,
+        config.max_iter,
+        config.threshold,
+        config.formula,
+        config.exponent,
+    );
+    draw_path(imgbuf, config, &points, color);
+}
+
+/// Draws the polyline through `points` of the plane over `imgbuf`, clipped to
+/// the image like `draw_orbit`.
+pub fn draw_path(
+    imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    config: &RenderConfig,
+    points: &[C64],
+    color: Rgb<u8>,
+) {
+    let points: Vec<(f64, f64)> = points
+        .iter()
+        .filter_map(|z| config.complex_to_plane(*z))
+        .collect();
+    for segment in points.windows(2) {
+        let (width, height) = (config.width as f64, config.height as f64);
+        if let Some((start, end)) = clip_segment(segment[0], segment[1], width, height) {
+            draw_segment(imgbuf, start, end, color);
+        }
+    }
+}
+
+/* Liang-Barsky against [0, width) x [0, height) */
+fn clip_segment(
+    start: (f64, f64),
+    end: (f64, f64),
+    width: f64,
+    height: f64,
+) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let (mut t0, mut t1): (f64, f64) = (0.0, 1.0);
+    /* Keep the far edge just inside so the end pixel indexes the buffer */
+    let edges: [(f64, f64); 4] = [
+        (-dx, start.0),
+        (dx, width - 1e-9 - start.0),
+        (-dy, start.1),
+        (dy, height - 1e-9 - start.1),
+    ];
+    for (p, q) in edges {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            t0 = t0.max(t);
+        } else {
+            t1 = t1.min(t);
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+    let at = |t: f64| (start.0 + t * dx, start.1 + t * dy);
+    Some((at(t0), at(t1)))
+}
+
+fn draw_segment(
+    imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    start: (f64, f64),
+    end: (f64, f64),
+    color: Rgb<u8>,
+) {
+    /* One step per pixel along the longer axis */
+    let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).ceil() as i64;
+    for i in 0..=steps {
+        let t = if steps == 0 {
+            0.0
+        } else {
+            i as f64 / steps as f64
+        };
+        let x = start.0 + t * (end.0 - start.0);
+        let y = start.1 + t * (end.1 - start.1);
+        imgbuf.put_pixel(x as u32, y as u32, color);
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use image::Rgb;
+use lerp::Lerp;
+use ordered_float::NotNan;
+
+use crate::color::{BlendSpace, MathyColor};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PaletteError {
+    Empty,
+    TooFewStops(usize),
+    KeyOutOfRange(f64),
+    MissingStart(f64),
+    MissingEnd(f64),
+    Seam,
+    Gradient(String),
+    /* (exponents given, segments in the palette) */
+    EasingCount(usize, usize),
+    /* (1-based stop number, stop text, problem) in an inline gradient */
+    Stop(usize, String, String),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaletteError::Empty => write!(f, "palette has no colors"),
+            PaletteError::TooFewStops(count) => {
+                write!(f, "palette has {count} distinct stop(s), needs at least 2")
+            }
+            PaletteError::KeyOutOfRange(key) => {
+                write!(f, "palette key {key} is outside [0, 1]")
+            }
+            PaletteError::MissingStart(key) => {
+                write!(f, "palette starts at {key} instead of 0")
+            }
+            PaletteError::MissingEnd(key) => write!(f, "palette ends at {key} instead of 1"),
+            PaletteError::Seam => write!(f, "palette starts and ends in different colors"),
+            PaletteError::Gradient(message) => write!(f, "invalid gradient file: {message}"),
+            PaletteError::EasingCount(given, segments) => write!(
+                f,
+                "{given} easing exponent(s) for a palette with {segments} segment(s)"
+            ),
+            PaletteError::Stop(number, stop, message) => {
+                write!(f, "gradient stop {number} '{stop}': {message}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+#[derive(Debug, Clone)]
+pub struct Palette {
+    /* Collection of x, color pairs with 0 <= x <= 1 */
+    _keys: BTreeSet<NotNan<f64>>,
+    _key_map: HashMap<NotNan<f64>, Rgb<u8>>,
+    /* Exponent on the blend from a key to the next one; keys left out blend linearly */
+    _easing: HashMap<NotNan<f64>, f64>,
+    _space: BlendSpace,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::new()
+    }
+}
+
+impl Palette {
+    pub fn new() -> Palette {
+        Palette {
+            _keys: BTreeSet::new(),
+            _key_map: HashMap::new(),
+            _easing: HashMap::new(),
+            _space: BlendSpace::Rgb,
+        }
+    }
+
+    /// Samples `f` at `n` evenly spaced keys from 0.0 to 1.0, which gives a
+    /// palette that already passes `validate`. `n` must be at least 2.
+    pub fn from_fn<F>(n: usize, f: F) -> Palette
+    where
+        F: Fn(f64) -> Rgb<u8>,
+    {
+        assert!(n >= 2, "a palette needs at least two stops");
+        let stops: Vec<(NotNan<f64>, Rgb<u8>)> = (0..n)
+            .map(|i| {
+                let x: f64 = i as f64 / (n - 1) as f64;
+                (NotNan::new(x).unwrap(), f(x))
+            })
+            .collect();
+        Palette::from_stops(stops).unwrap()
+    }
+
+    /// Builds a palette that is ready for `get_color`: at least two distinct
+    /// keys, all within [0, 1], with stops at both 0.0 and 1.0. A key given
+    /// twice keeps its last color.
+    pub fn from_stops(stops: Vec<(NotNan<f64>, Rgb<u8>)>) -> Result<Palette, PaletteError> {
+        let mut palette = Palette::new();
+        for (key, color) in &stops {
+            palette.add_col(*key, color);
+        }
+        if palette._keys.len() < 2 {
+            return Err(PaletteError::TooFewStops(palette._keys.len()));
+        }
+        palette.validate(false)?;
+        Ok(palette)
+    }
+
+    /// Reads a GIMP .ggr gradient. Unsupported blend and coloring types are
+    /// drawn as linear RGB with a warning on stderr.
+    pub fn from_ggr(path: &Path) -> Result<Palette, PaletteError> {
+        let text = std::fs::read_to_string(path).map_err(|e| {
+            PaletteError::Gradient(format!("couldn't read {}: {e}", path.display()))
+        })?;
+        let stops = crate::ggr::parse_stops(&text).map_err(PaletteError::Gradient)?;
+        Palette::from_stops(stops)
+    }
+
+    /// Parses an inline gradient of comma-separated `PERCENT%:RRGGBB` stops,
+    /// such as `0%:#000000, 30%:#ff8800, 100%:#ffffff`, which must cover 0%
+    /// to 100% like `from_stops` requires.
+    pub fn from_css(text: &str) -> Result<Palette, PaletteError> {
+        let stops: Vec<(NotNan<f64>, Rgb<u8>)> = text
+            .split(',')
+            .enumerate()
+            .map(|(i, stop)| {
+                let error =
+                    |message: String| PaletteError::Stop(i + 1, stop.trim().into(), message);
+                let (key, color) = stop
+                    .split_once(':')
+                    .ok_or_else(|| error("expected PERCENT%:RRGGBB".into()))?;
+                let percent: f64 = key
+                    .trim()
+                    .strip_suffix('%')
+                    .and_then(|number| number.trim().parse().ok())
+                    .filter(|percent: &f64| percent.is_finite())
+                    .ok_or_else(|| error(format!("'{}' is not a percentage", key.trim())))?;
+                if !(0.0..=100.0).contains(&percent) {
+                    return Err(error(format!("{percent}% is outside 0% to 100%")));
+                }
+                let color = parse_hex_color(color).map_err(error)?;
+                Ok((NotNan::new(percent / 100.0).unwrap(), color))
+            })
+            .collect::<Result<_, _>>()?;
+        Palette::from_stops(stops)
+    }
+
+    pub fn add_col(&mut self, key: NotNan<f64>, new_color: &Rgb<u8>) {
+        self._keys.insert(key);
+        self._key_map.insert(key, new_color.to_owned());
+    }
+
+    /// Raises the blend factor of the segment starting at `key` to `exponent`
+    /// before interpolating. 1.0 is a linear blend; larger values hold the
+    /// start color longer and smaller ones reach the end color sooner.
+    pub fn set_easing(&mut self, key: NotNan<f64>, exponent: f64) {
+        self._easing.insert(key, exponent);
+    }
+
+    /// Sets one easing exponent per segment in key order, or the same one on
+    /// every segment when a single exponent is given.
+    pub fn ease_segments(&mut self, exponents: &[f64]) -> Result<(), PaletteError> {
+        /* Every key but the last starts a segment */
+        let mut starts: Vec<NotNan<f64>> = self._keys.iter().copied().collect();
+        starts.pop();
+        if exponents.len() != 1 && exponents.len() != starts.len() {
+            return Err(PaletteError::EasingCount(exponents.len(), starts.len()));
+        }
+        for (i, key) in starts.into_iter().enumerate() {
+            self.set_easing(key, exponents[i.min(exponents.len() - 1)]);
+        }
+        Ok(())
+    }
+
+    /// Blends between stops in `space` from now on. The stops themselves keep
+    /// their colors exactly.
+    pub fn set_blend_space(&mut self, space: BlendSpace) {
+        self._space = space;
+    }
+
+    /// Checks that every key lies in [0, 1] and that stops exist at both ends,
+    /// which is what `get_color` relies on. With `extend`, missing end stops
+    /// are filled in by repeating the first and last colors.
+    pub fn validate(&mut self, extend: bool) -> Result<(), PaletteError> {
+        let (Some(&first), Some(&last)) = (self._keys.first(), self._keys.last()) else {
+            return Err(PaletteError::Empty);
+        };
+        if let Some(key) = self
+            ._keys
+            .iter()
+            .find(|k| !(0.0..=1.0).contains(&k.into_inner()))
+        {
+            return Err(PaletteError::KeyOutOfRange(key.into_inner()));
+        }
+        let zero = NotNan::new(0.0).unwrap();
+        let one = NotNan::new(1.0).unwrap();
+        if first != zero {
+            if !extend {
+                return Err(PaletteError::MissingStart(first.into_inner()));
+            }
+            let color = self._key_map[&first];
+            self.add_col(zero, &color);
+        }
+        if last != one {
+            if !extend {
+                return Err(PaletteError::MissingEnd(last.into_inner()));
+            }
+            let color = self._key_map[&last];
+            self.add_col(one, &color);
+        }
+        Ok(())
+    }
+
+    /// Closes the palette into a loop by adding a stop at 1.0 with the color
+    /// at 0.0, so positions can wrap around without a visible seam. A palette
+    /// that already has a stop at 1.0 must end in its start color.
+    pub fn make_cyclic(&mut self) -> Result<(), PaletteError> {
+        let zero = NotNan::new(0.0).unwrap();
+        let one = NotNan::new(1.0).unwrap();
+        let Some(&first) = self._keys.first() else {
+            return Err(PaletteError::Empty);
+        };
+        if first != zero {
+            return Err(PaletteError::MissingStart(first.into_inner()));
+        }
+        let start = self._key_map[&zero];
+        match self._key_map.get(&one) {
+            Some(end) if *end != start => Err(PaletteError::Seam),
+            Some(_) => Ok(()),
+            None => {
+                self.add_col(one, &start);
+                Ok(())
+            }
+        }
+    }
+
+    /// Color at position `k`. The palette must have at least one stop and a
+    /// stop at or past `k`; `from_stops` and `validate` guarantee both for
+    /// every `k` in [0, 1]. Panics otherwise.
+    pub fn get_color(&self, k: NotNan<f64>) -> Rgb<u8> {
+        self.get_mathy_color(k).unwrap()
+    }
+
+    pub fn get_mathy_color(&self, k: NotNan<f64>) -> MathyColor<f64> {
+        let mut prev_key: &NotNan<f64> = self._keys.first().unwrap();
+
+        if k <= *prev_key {
+            return MathyColor::from_ref(self._key_map.get(prev_key).unwrap());
+        }
+
+        for cur_key in &self._keys {
+            if cur_key >= &k {
+                let prev_color: &Rgb<u8> = self._key_map.get(prev_key).unwrap();
+                let cur_color: &Rgb<u8> = self._key_map.get(cur_key).unwrap();
+                let mut interpolation_factor: NotNan<f64> = (k - prev_key) / (cur_key - prev_key);
+                if let Some(exponent) = self._easing.get(prev_key) {
+                    interpolation_factor =
+                        NotNan::new(interpolation_factor.powf(*exponent)).unwrap();
+                }
+                let prev_color_mathy: MathyColor<f64> = MathyColor::from_ref(prev_color);
+                let cur_color_mathy: MathyColor<f64> = MathyColor::from_ref(cur_color);
+                return match self._space {
+                    BlendSpace::Rgb => {
+                        prev_color_mathy.lerp(cur_color_mathy, *interpolation_factor)
+                    }
+                    BlendSpace::Lab => prev_color_mathy
+                        .srgb_to_lab()
+                        .lerp(cur_color_mathy.srgb_to_lab(), *interpolation_factor)
+                        .lab_to_srgb(),
+                };
+            }
+            prev_key = cur_key;
+        }
+        panic!()
+    }
+}
+
+/// Three sine waves out of phase, fading to black at 1.0 so the set stays dark.
+pub fn sine(x: f64) -> Rgb<u8> {
+    let channel = |phase: f64| -> u8 {
+        let wave: f64 = 0.5 + 0.5 * (2.0 * std::f64::consts::PI * (3.0 * x + phase)).sin();
+        ((1.0 - x) * wave * 255.0).round() as u8
+    };
+    Rgb([channel(0.0), channel(1.0 / 3.0), channel(2.0 / 3.0)])
+}
+
+/// Parses `RRGGBB` or `#RRGGBB`.
+pub fn parse_hex_color(s: &str) -> Result<Rgb<u8>, String> {
+    let hex = s.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        return Err(format!("'{s}' is not a RRGGBB hex color"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    Ok(Rgb([channel(0), channel(2), channel(4)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(k: f64) -> NotNan<f64> {
+        NotNan::new(k).unwrap()
+    }
+
+    #[test]
+    fn make_cyclic_closes_the_seam() {
+        let mut palette = Palette::new();
+        palette.add_col(key(0.0), &Rgb([10, 20, 30]));
+        palette.add_col(key(0.5), &Rgb([200, 100, 0]));
+        palette.make_cyclic().unwrap();
+        assert_eq!(palette.get_color(key(0.0)), palette.get_color(key(1.0)));
+    }
+
+    #[test]
+    fn invalid_palettes_report_their_flaw() {
+        let red = Rgb([255, 0, 0]);
+        let blue = Rgb([0, 0, 255]);
+        let palette = |stops: &[(f64, Rgb<u8>)]| {
+            let mut palette = Palette::new();
+            for (k, color) in stops {
+                palette.add_col(key(*k), color);
+            }
+            palette
+        };
+
+        assert_eq!(
+            Palette::from_stops(vec![(key(0.0), red), (key(0.0), blue)]).err(),
+            Some(PaletteError::TooFewStops(1))
+        );
+        assert_eq!(
+            palette(&[(0.0, red), (1.5, blue)]).validate(true),
+            Err(PaletteError::KeyOutOfRange(1.5))
+        );
+        assert_eq!(
+            palette(&[(0.25, red), (1.0, blue)]).validate(false),
+            Err(PaletteError::MissingStart(0.25))
+        );
+        assert_eq!(
+            palette(&[(0.0, red), (0.75, blue)]).validate(false),
+            Err(PaletteError::MissingEnd(0.75))
+        );
+        assert_eq!(
+            palette(&[(0.0, red), (1.0, blue)]).make_cyclic(),
+            Err(PaletteError::Seam)
+        );
+        assert_eq!(Palette::new().validate(true), Err(PaletteError::Empty));
+        assert_eq!(Palette::new().make_cyclic(), Err(PaletteError::Empty));
+    }
+
+    #[test]
+    fn css_errors_name_the_stop() {
+        let error: String = Palette::from_css("0%:#000000, 150%:#ffffff")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            error,
+            "gradient stop 2 '150%:#ffffff': 150% is outside 0% to 100%"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use clap::ValueEnum;
+use image::{DynamicImage, ImageBuffer, Rgb};
+
+use crate::color::MathyColor;
+
+/// Channel layout and depth of the saved image.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb8,
+    Rgb16,
+    Rgba8,
+    Rgba16,
+    Gray8,
+    Gray16,
+    /// 32-bit float R, G and B in linear light, for EXR output only
+    Rgb32f,
+    /// Rgb32f plus an opaque alpha channel
+    Rgba32f,
+}
+
+impl PixelFormat {
+    /// Whether the format keeps more precision than the 8-bit render buffer.
+    pub fn is_wide(self) -> bool {
+        self != PixelFormat::Rgb8 && self != PixelFormat::Rgba8 && self != PixelFormat::Gray8
+    }
+
+    /// Whether the format stores floats, which only OpenEXR can hold.
+    pub fn is_float(self) -> bool {
+        matches!(self, PixelFormat::Rgb32f | PixelFormat::Rgba32f)
+    }
+
+    /// Converts the finished 8-bit image. Wide formats take their values from
+    /// `fine`, the unquantized colors in row-major order, wherever a pixel is
+    /// still the rounding of its fine color; pixels an overlay painted over
+    /// keep their 8-bit value. Alpha is always opaque and gray is luminance.
+    ///
+    /// Float formats are decoded from sRGB into linear light, as compositing
+    /// expects, with 1.0 for a full channel, so nothing is lost to quantization.
+    pub fn convert(
+        self,
+        imgbuf: ImageBuffer<Rgb<u8>, Vec<u8>>,
+        fine: Option<&[MathyColor<f64>]>,
+    ) -> DynamicImage {
+        if !self.is_wide() {
+            let image = DynamicImage::ImageRgb8(imgbuf);
+            return match self {
+                PixelFormat::Rgba8 => DynamicImage::ImageRgba8(image.into_rgba8()),
+                PixelFormat::Gray8 => DynamicImage::ImageLuma8(image.into_luma8()),
+                _ => image,
+            };
+        }
+        let width = imgbuf.width();
+        /* The fine color where it is still what the pixel shows */
+        let color_at = |x: u32, y: u32| -> MathyColor<f64> {
+            let pixel = imgbuf.get_pixel(x, y);
+            match fine.map(|fine| fine[(y * width + x) as usize]) {
+                Some(color) if color.unwrap() == *pixel => color,
+                _ => MathyColor::from_ref(pixel),
+            }
+        };
+        if self.is_float() {
+            let float: ImageBuffer<Rgb<f32>, Vec<f32>> =
+                ImageBuffer::from_fn(width, imgbuf.height(), |x, y| {
+                    let color = color_at(x, y).srgb_to_linear();
+                    Rgb([color.r, color.g, color.b].map(|c: f64| (c / 255.0) as f32))
+                });
+            let image = DynamicImage::ImageRgb32F(float);
+            return match self {
+                PixelFormat::Rgba32f => DynamicImage::ImageRgba32F(image.into_rgba32f()),
+                _ => image,
+            };
+        }
+        let wide: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(width, imgbuf.height(), |x, y| {
+                let color = color_at(x, y);
+                Rgb([color.r, color.g, color.b]
+                    .map(|c: f64| (c / 255.0 * 65535.0).round().clamp(0.0, 65535.0) as u16))
+            });
+        let image = DynamicImage::ImageRgb16(wide);
+        match self {
+            PixelFormat::Rgba16 => DynamicImage::ImageRgba16(image.into_rgba16()),
+            PixelFormat::Gray16 => DynamicImage::ImageLuma16(image.into_luma16()),
+            _ => image,
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::ops::Range;
+
+use clap::ValueEnum;
+
+use num_traits::ToPrimitive;
+
+use crate::{plane_scales, RenderConfig, C64};
+
+/// Order in which pixels are visited. The image is the same either way; the
+/// order only decides which pixels are computed together.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Order {
+    /// Left to right, top to bottom
+    RowMajor,
+    /// Square rings outward from the middle, for progressive display
+    Spiral,
+    /// Along a Hilbert curve, so neighbors in time are neighbors in space
+    Hilbert,
+}
+
+/// How PixelCoordinates finds each pixel's point.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Coordinates {
+    /// From the exact rational coordinate, correctly rounded
+    Exact,
+    /// From f64 steps per pixel; faster, but points can be an ulp or so off
+    Stepped,
+}
+
+/// Yields `(x, y, point)` for every pixel of `rows` in the chosen order,
+/// where `point` is `config.pixel_to_complex(x, y)`, or its f64 approximation
+/// with Coordinates::Stepped.
+pub struct PixelCoordinates<'a> {
+    config: &'a RenderConfig,
+    pixels: std::vec::IntoIter<(i32, i32)>,
+    /* Base-plane coordinate of pixel (0, 0) and the step to the next pixel,
+    for Coordinates::Stepped */
+    steps: Option<(C64, C64)>,
+}
+
+impl<'a> PixelCoordinates<'a> {
+    pub fn new(config: &'a RenderConfig, rows: Range<i32>, order: Order) -> PixelCoordinates<'a> {
+        let width = config.width;
+        let mut pixels: Vec<(i32, i32)> = match order {
+            Order::RowMajor | Order::Spiral => {
+                rows.flat_map(|y| (0..width).map(move |x| (x, y))).collect()
+            }
+            Order::Hilbert => hilbert(width, rows),
+        };
+        if order == Order::Spiral {
+            /* Ring index first, then angle, so each ring is swept in one turn */
+            let (cx, cy) = (width as f64 / 2.0, config.height as f64 / 2.0);
+            pixels.sort_by_cached_key(|&(x, y)| {
+                let (dx, dy) = (x as f64 + 0.5 - cx, y as f64 + 0.5 - cy);
+                let ring = dx.abs().max(dy.abs()) as i64;
+                let angle = (dy.atan2(dx) * 1e6) as i64;
+                (ring, angle)
+            });
+        }
+        let steps = (config.coordinates == Coordinates::Stepped).then(|| {
+            let (x_scale, y_scale) = plane_scales(config);
+            let (x_scale, y_scale) = (x_scale.to_f64().unwrap(), y_scale.to_f64().unwrap());
+            let step = C64::new(
+                2.0 * x_scale / config.width as f64,
+                2.0 * y_scale / config.height as f64,
+            );
+            (C64::new(-x_scale, -y_scale), step)
+        });
+        PixelCoordinates {
+            config,
+            pixels: pixels.into_iter(),
+            steps,
+        }
+    }
+}
+
+impl Iterator for PixelCoordinates<'_> {
+    type Item = (i32, i32, C64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (x, y) = self.pixels.next()?;
+        let point = match self.steps {
+            Some((origin, step)) => self.config.map_base(C64::new(
+                origin.re + x as f64 * step.re,
+                origin.im + y as f64 * step.im,
+            )),
+            None => self.config.pixel_to_complex(x, y),
+        };
+        Some((x, y, point))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pixels.size_hint()
+    }
+}
+
+/* Walks a power-of-two square covering the band and keeps the pixels inside it */
+fn hilbert(width: i32, rows: Range<i32>) -> Vec<(i32, i32)> {
+    let band: i32 = rows.end - rows.start;
+    let side: i64 = (width.max(band).max(1) as u64).next_power_of_two() as i64;
+    (0..side * side)
+        .map(|d| {
+            /* Standard d -> (x, y) conversion, rotating each quadrant into place */
+            let (mut x, mut y, mut t) = (0i64, 0i64, d);
+            let mut s: i64 = 1;
+            while s < side {
+                let rx = 1 & (t / 2);
+                let ry = 1 & (t ^ rx);
+                if ry == 0 {
+                    if rx == 1 {
+                        x = s - 1 - x;
+                        y = s - 1 - y;
+                    }
+                    std::mem::swap(&mut x, &mut y);
+                }
+                x += s * rx;
+                y += s * ry;
+                t /= 4;
+                s *= 2;
+            }
+            (x, y)
+        })
+        .filter(|&(x, y)| x < width as i64 && y < band as i64)
+        .map(|(x, y)| (x as i32, rows.start + y as i32))
+        .collect()
+}
@@ -0,0 +1,115 @@
+use std::f64::consts::TAU;
+
+use image::{ImageBuffer, Rgb};
+
+use crate::{overlay, RenderConfig, C64, ITERMAX};
+
+/* |z| rays start from; large enough that the potential is close to log |z| */
+const RAY_ESCAPE_RADIUS: f64 = 65536.0;
+/* Points traced per level, where each level halves the log potential */
+const RAY_SHARPNESS: u32 = 8;
+/* Newton steps spent on each point; a couple usually converge */
+const RAY_NEWTON_STEPS: u32 = 16;
+/* Denominators up to this keep doubling the numerator inside a u64 */
+const MAX_ANGLE_DENOM: u64 = 1 << 62;
+
+/// An external angle in turns, kept as a fraction so repeated doubling stays
+/// exact for periodic angles such as 1/3.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Angle {
+    numer: u64,
+    denom: u64,
+}
+
+impl Angle {
+    /* The angle z^2 maps this one to */
+    fn double(self) -> Angle {
+        Angle {
+            numer: 2 * self.numer % self.denom,
+            denom: self.denom,
+        }
+    }
+
+    fn turns(self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+}
+
+/// Parses an angle in turns, as a fraction `p/q` or a decimal; whole turns
+/// wrap around, so 4/3 is 1/3. Decimals are doubled as the nearest binary
+/// fraction, which reaches 0 after 52 levels.
+pub fn parse_angle(s: &str) -> Result<Angle, String> {
+    let invalid = || format!("'{s}' is not an angle in turns such as 1/3 or 0.25");
+    let s = s.trim();
+    if let Some((p, q)) = s.split_once('/') {
+        let (p, q): (u64, u64) = (
+            p.trim().parse().map_err(|_| invalid())?,
+            q.trim().parse().map_err(|_| invalid())?,
+        );
+        if q == 0 || q > MAX_ANGLE_DENOM {
+            return Err(format!("denominator of '{s}' must be in 1..=2^62"));
+        }
+        return Ok(Angle {
+            numer: p % q,
+            denom: q,
+        });
+    }
+    match s.parse::<f64>() {
+        Ok(turns) if turns >= 0.0 && turns.is_finite() => {
+            let denom: u64 = 1 << 52;
+            Ok(Angle {
+                numer: (turns.fract() * denom as f64).round() as u64 % denom,
+                denom,
+            })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Points of the Mandelbrot set's external ray at `angle`, traced inward from
+/// radius RAY_ESCAPE_RADIUS for ITERMAX levels. The point at level `j` is the
+/// c for which z_j(c) lies on the circle of the current radius at angle
+/// 2^(j-1) `angle`, found by Newton's method from the previous point. The
+/// trace stops early if Newton's method breaks down.
+pub fn trace(angle: Angle) -> Vec<C64> {
+    let mut c = C64::from_polar(RAY_ESCAPE_RADIUS, TAU * angle.turns());
+    let mut angle = angle;
+    let mut points = vec![c];
+    for j in 1..=ITERMAX {
+        for k in 0..RAY_SHARPNESS {
+            let exponent: f64 = 0.5f64.powf((k as f64 + 0.5) / RAY_SHARPNESS as f64);
+            let target = C64::from_polar(RAY_ESCAPE_RADIUS.powf(exponent), TAU * angle.turns());
+            for _ in 0..RAY_NEWTON_STEPS {
+                let (mut z, mut dz) = (C64::new(0.0, 0.0), C64::new(0.0, 0.0));
+                for _ in 0..j {
+                    dz = 2.0 * z * dz + 1.0;
+                    z = z * z + c;
+                }
+                let next: C64 = c - (z - target) / dz;
+                if !next.is_finite() {
+                    return points;
+                }
+                let converged = next == c;
+                c = next;
+                if converged {
+                    break;
+                }
+            }
+            points.push(c);
+        }
+        angle = angle.double();
+    }
+    points
+}
+
+/// Draws the external ray at each of `angles` over `imgbuf`.
+pub fn draw_rays(
+    imgbuf: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    config: &RenderConfig,
+    angles: &[Angle],
+    color: Rgb<u8>,
+) {
+    for angle in angles {
+        overlay::draw_path(imgbuf, config, &trace(*angle), color);
+    }
+}
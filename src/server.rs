@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use image::{ImageBuffer, ImageFormat, Rgb};
+use num_traits::ToPrimitive;
+use rayon::prelude::*;
+use tiny_http::{Header, Request, Response, Server};
+
+use crate::{live, tiles, Palette, RenderConfig, Viewport, C64};
+
+/* Edge length of a map tile in pixels, as slippy-map clients expect */
+const TILE_PIXELS: i32 = 256;
+/* Rendered tiles kept in memory; the cache is emptied when it fills up */
+const CACHE_TILES: usize = 4096;
+
+/// Serves `/tile/{z}/{x}/{y}.png` on `port` until the process is killed.
+/// Zoom level 0 is a single tile covering the square of half-size
+/// `half_height / zoom` around the view center; each level splits every tile
+/// into four. Requests are rendered on the rayon pool and cached. With
+/// `live_port`, WebSocket previews are served there as well; see `live::run`.
+pub fn run(config: &RenderConfig, palette: &Palette, port: u16, live_port: Option<u16>) {
+    std::thread::scope(|scope| {
+        if let Some(live_port) = live_port {
+            scope.spawn(move || live::run(config, palette, live_port));
+        }
+        serve_tiles(config, palette, port);
+    });
+}
+
+fn serve_tiles(config: &RenderConfig, palette: &Palette, port: u16) {
+    let server = Server::http(("0.0.0.0", port)).unwrap();
+    let cache: Mutex<HashMap<(u32, u32, u32), Vec<u8>>> = Mutex::new(HashMap::new());
+    eprintln!("serving tiles on http://localhost:{port}/tile/{{z}}/{{x}}/{{y}}.png");
+
+    server
+        .incoming_requests()
+        .par_bridge()
+        .for_each(|request: Request| {
+            let Some(key) = parse_tile_url(request.url()) else {
+                request.respond(Response::empty(404)).unwrap_or_default();
+                return;
+            };
+            let cached = cache.lock().unwrap().get(&key).cloned();
+            let bytes = cached.unwrap_or_else(|| {
+                let bytes = render_tile(config, palette, key);
+                let mut cache = cache.lock().unwrap();
+                if cache.len() >= CACHE_TILES {
+                    cache.clear();
+                }
+                cache.insert(key, bytes.clone());
+                bytes
+            });
+            let content_type = Header::from_bytes("Content-Type", "image/png").unwrap();
+            request
+                .respond(Response::from_data(bytes).with_header(content_type))
+                .unwrap_or_default();
+        });
+}
+
+fn parse_tile_url(url: &str) -> Option<(u32, u32, u32)> {
+    let rest = url.strip_prefix("/tile/")?.strip_suffix(".png")?;
+    let parts: Vec<u32> = rest
+        .split('/')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let [z, x, y] = parts[..] else {
+        return None;
+    };
+    /* 2^z tiles per side, and zooms past 2^62 are beyond f64 anyway */
+    (z < 63 && x < 1 << z && y < 1 << z).then_some((z, x, y))
+}
+
+fn render_tile(config: &RenderConfig, palette: &Palette, (z, x, y): (u32, u32, u32)) -> Vec<u8> {
+    let world: Viewport = config.viewport.unwrap_or_default();
+    let tiles_per_side: f64 = (1u64 << z) as f64;
+    /* Offset of the tile center from the world center, in tile widths */
+    let offset_x: f64 = x as f64 + 0.5 - tiles_per_side / 2.0;
+    let offset_y: f64 = y as f64 + 0.5 - tiles_per_side / 2.0;
+    let tile_size: f64 = 2.0 * config.half_height.to_f64().unwrap() / world.zoom / tiles_per_side;
+    let viewport = Viewport {
+        /* Tile rows grow downwards like image rows */
+        center: world.center + world.turn() * C64::new(offset_x, -offset_y) * tile_size,
+        zoom: world.zoom * tiles_per_side,
+        ..world
+    };
+    let tile_config = RenderConfig {
+        width: TILE_PIXELS,
+        height: TILE_PIXELS,
+        viewport: Some(viewport),
+        deep: None,
+        ..config.clone()
+    }
+    .with_precision();
+
+    let mut imgbuf: ImageBuffer<Rgb<u8>, Vec<u8>> =
+        ImageBuffer::new(TILE_PIXELS as u32, TILE_PIXELS as u32);
+    let tile: Mutex<Option<tiles::Tile>> = Mutex::new(None);
+    tiles::render_tiles(&tile_config, palette, TILE_PIXELS, |done: tiles::Tile| {
+        *tile.lock().unwrap() = Some(done);
+    });
+    let tile = tile.into_inner().unwrap().unwrap();
+    for (pixel, color) in imgbuf.pixels_mut().zip(tile.pixels) {
+        *pixel = color;
+    }
+
+    let mut bytes = Cursor::new(Vec::new());
+    imgbuf.write_to(&mut bytes, ImageFormat::Png).unwrap();
+    bytes.into_inner()
+}
@@ -0,0 +1,102 @@
+use image::{ImageBuffer, Rgb};
+use ordered_float::NotNan;
+
+use mandelbrot::Palette;
+
+/* Layout of one row of the sheet, in pixels */
+const STRIP_WIDTH: u32 = 512;
+const STRIP_HEIGHT: u32 = 40;
+const LABEL_WIDTH: u32 = 160;
+const MARGIN: u32 = 8;
+/* Each font pixel is drawn as a square this many pixels wide */
+const GLYPH_SCALE: u32 = 4;
+const BACKGROUND: Rgb<u8> = Rgb([24, 24, 24]);
+const INK: Rgb<u8> = Rgb([235, 235, 235]);
+
+/// A `width` x `height` strip running through the palette from 0 on the left
+/// to 1 on the right.
+pub fn strip(palette: &Palette, width: u32, height: u32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let colors: Vec<Rgb<u8>> = (0..width)
+        .map(|x| {
+            let position = x as f64 / (width - 1).max(1) as f64;
+            palette.get_color(NotNan::new(position).unwrap())
+        })
+        .collect();
+    ImageBuffer::from_fn(width, height, |x, _| colors[x as usize])
+}
+
+/// One labeled strip per palette, stacked top to bottom in the given order.
+/// Labels are drawn in a small built-in capital font; characters it lacks are
+/// left blank.
+pub fn contact_sheet(palettes: &[(String, Palette)]) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let width = MARGIN + LABEL_WIDTH + STRIP_WIDTH + MARGIN;
+    let height = MARGIN + palettes.len() as u32 * (STRIP_HEIGHT + MARGIN);
+    let mut sheet = ImageBuffer::from_pixel(width, height, BACKGROUND);
+    for (row, (name, palette)) in palettes.iter().enumerate() {
+        let top = MARGIN + row as u32 * (STRIP_HEIGHT + MARGIN);
+        let label_top = top + (STRIP_HEIGHT - 5 * GLYPH_SCALE) / 2;
+        draw_label(&mut sheet, name, MARGIN, label_top);
+        let strip = strip(palette, STRIP_WIDTH, STRIP_HEIGHT);
+        for (x, y, pixel) in strip.enumerate_pixels() {
+            sheet.put_pixel(MARGIN + LABEL_WIDTH + x, top + y, *pixel);
+        }
+    }
+    sheet
+}
+
+fn draw_label(sheet: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, text: &str, left: u32, top: u32) {
+    for (i, ch) in text.chars().enumerate() {
+        let x0 = left + i as u32 * 4 * GLYPH_SCALE;
+        if x0 + 3 * GLYPH_SCALE > MARGIN + LABEL_WIDTH {
+            break;
+        }
+        for (gy, line) in glyph(ch).iter().enumerate() {
+            for (gx, cell) in line.bytes().enumerate() {
+                if cell != b'#' {
+                    continue;
+                }
+                for dy in 0..GLYPH_SCALE {
+                    for dx in 0..GLYPH_SCALE {
+                        let x = x0 + gx as u32 * GLYPH_SCALE + dx;
+                        let y = top + gy as u32 * GLYPH_SCALE + dy;
+                        sheet.put_pixel(x, y, INK);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/* 3x5 capitals, enough for palette names */
+fn glyph(ch: char) -> [&'static str; 5] {
+    match ch.to_ascii_uppercase() {
+        'A' => [".#.", "#.#", "###", "#.#", "#.#"],
+        'B' => ["##.", "#.#", "##.", "#.#", "##."],
+        'C' => [".##", "#..", "#..", "#..", ".##"],
+        'D' => ["##.", "#.#", "#.#", "#.#", "##."],
+        'E' => ["###", "#..", "##.", "#..", "###"],
+        'F' => ["###", "#..", "##.", "#..", "#.."],
+        'G' => [".##", "#..", "#.#", "#.#", ".##"],
+        'H' => ["#.#", "#.#", "###", "#.#", "#.#"],
+        'I' => ["###", ".#.", ".#.", ".#.", "###"],
+        'J' => ["..#", "..#", "..#", "#.#", ".#."],
+        'K' => ["#.#", "#.#", "##.", "#.#", "#.#"],
+        'L' => ["#..", "#..", "#..", "#..", "###"],
+        'M' => ["#.#", "###", "###", "#.#", "#.#"],
+        'N' => ["##.", "#.#", "#.#", "#.#", "#.#"],
+        'O' => [".#.", "#.#", "#.#", "#.#", ".#."],
+        'P' => ["##.", "#.#", "##.", "#..", "#.."],
+        'Q' => [".#.", "#.#", "#.#", "###", ".##"],
+        'R' => ["##.", "#.#", "##.", "#.#", "#.#"],
+        'S' => [".##", "#..", ".#.", "..#", "##."],
+        'T' => ["###", ".#.", ".#.", ".#.", ".#."],
+        'U' => ["#.#", "#.#", "#.#", "#.#", "###"],
+        'V' => ["#.#", "#.#", "#.#", "#.#", ".#."],
+        'W' => ["#.#", "#.#", "###", "###", "#.#"],
+        'X' => ["#.#", "#.#", ".#.", "#.#", "#.#"],
+        'Y' => ["#.#", "#.#", ".#.", ".#.", ".#."],
+        'Z' => ["###", "..#", ".#.", "#..", "###"],
+        '-' => ["...", "...", "###", "...", "..."],
+        _ => ["..."; 5],
+    }
+}
@@ -0,0 +1,70 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::{checkpoint, RenderConfig, STDOUT_PATH};
+
+/// Figures from one escape-time render, serialized for scripts that track
+/// performance across runs.
+#[derive(Debug, Serialize)]
+pub struct RenderStats {
+    width: i32,
+    height: i32,
+    /* The checkpoint hash, so runs with equal escape settings share it */
+    config_hash: String,
+    phases: Vec<Phase>,
+    total_ms: f64,
+    in_set_fraction: f64,
+    /* Sum of escape counts, with max_iter for each point inside */
+    iterations: u64,
+    max_iterations: i32,
+}
+
+#[derive(Debug, Serialize)]
+struct Phase {
+    name: &'static str,
+    ms: f64,
+}
+
+impl RenderStats {
+    pub fn new(
+        config: &RenderConfig,
+        velocities: &[Vec<i32>],
+        phases: &[(&'static str, Duration)],
+    ) -> RenderStats {
+        let counts = || velocities.iter().flatten();
+        let pixels = counts().count().max(1) as f64;
+        let inside = counts().filter(|v| **v >= config.max_iter).count() as f64;
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        RenderStats {
+            width: config.width,
+            height: config.height,
+            config_hash: format!("{:016x}", checkpoint::config_hash(config)),
+            phases: phases
+                .iter()
+                .map(|&(name, duration)| Phase {
+                    name,
+                    ms: ms(duration),
+                })
+                .collect(),
+            total_ms: ms(phases.iter().map(|(_, d)| *d).sum()),
+            in_set_fraction: inside / pixels,
+            iterations: counts().map(|v| *v as u64).sum(),
+            max_iterations: config.max_iter,
+        }
+    }
+
+    /// Writes the stats as a JSON object to `path`, or to stdout for STDOUT_PATH.
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let mut out: Box<dyn Write> = if path == STDOUT_PATH {
+            Box::new(io::stdout().lock())
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        out.flush()
+    }
+}
@@ -0,0 +1,93 @@
+use clap::ValueEnum;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/* Tries per Poisson sample before taking the best candidate found */
+const POISSON_ATTEMPTS: usize = 30;
+/* Standard deviation of the Gaussian filter, in pixels */
+const GAUSSIAN_SIGMA: f64 = 0.4;
+
+/// Where the N x N subsamples of a supersampled pixel are placed.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// Regular grid from the pixel corner; exact rational coordinates
+    Grid,
+    /// The regular grid turned by atan(1/2) so no two samples share a row or column
+    RotatedGrid,
+    /// Random points kept apart from each other, fixed per pixel
+    Poisson,
+}
+
+/// How much each subsample counts toward its pixel's color.
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Filter {
+    /// Every subsample counts the same
+    Box,
+    /// Weight falls off linearly to zero one pixel from the center
+    Tent,
+    /// Weight falls off as a Gaussian around the center
+    Gaussian,
+}
+
+impl Filter {
+    /// Weight of a subsample `dx`, `dy` pixels away from the pixel's center.
+    pub fn weight(self, dx: f64, dy: f64) -> f64 {
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => (1.0 - dx.abs()).max(0.0) * (1.0 - dy.abs()).max(0.0),
+            Filter::Gaussian => {
+                (-(dx * dx + dy * dy) / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA)).exp()
+            }
+        }
+    }
+}
+
+/// Offsets in [0, 1) x [0, 1) from the top-left corner of pixel (`w`, `h`),
+/// `samples * samples` of them. Poisson offsets are seeded by the pixel so a
+/// render is reproducible.
+pub fn offsets(pattern: Pattern, samples: i32, w: i32, h: i32) -> Vec<(f64, f64)> {
+    let n = samples as usize;
+    let grid = (0..n * n).map(move |k| {
+        let (i, j) = ((k % n) as f64, (k / n) as f64);
+        (i / n as f64, j / n as f64)
+    });
+    match pattern {
+        Pattern::Grid => grid.collect(),
+        Pattern::RotatedGrid => {
+            /* Rotate about the pixel center and wrap back into the pixel */
+            let angle: f64 = 0.5f64.atan();
+            let (sin, cos) = angle.sin_cos();
+            let center = 0.5 - 0.5 / n as f64;
+            grid.map(|(x, y)| {
+                let (dx, dy) = (x - center, y - center);
+                let rx = cos * dx - sin * dy + 0.5;
+                let ry = sin * dx + cos * dy + 0.5;
+                (rx.rem_euclid(1.0), ry.rem_euclid(1.0))
+            })
+            .collect()
+        }
+        Pattern::Poisson => poisson(n * n, w, h),
+    }
+}
+
+/* Best-candidate sampling: each new point is the candidate farthest from the rest */
+fn poisson(count: usize, w: i32, h: i32) -> Vec<(f64, f64)> {
+    let seed: u64 = ((w as u32 as u64) << 32) | h as u32 as u64;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut points: Vec<(f64, f64)> = Vec::with_capacity(count);
+    while points.len() < count {
+        let candidate = (0..POISSON_ATTEMPTS)
+            .map(|_| (rng.gen::<f64>(), rng.gen::<f64>()))
+            .max_by(|a, b| nearest(&points, *a).total_cmp(&nearest(&points, *b)))
+            .unwrap();
+        points.push(candidate);
+    }
+    points
+}
+
+fn nearest(points: &[(f64, f64)], p: (f64, f64)) -> f64 {
+    points
+        .iter()
+        .map(|q| (p.0 - q.0).powi(2) + (p.1 - q.1).powi(2))
+        .fold(f64::INFINITY, f64::min)
+}
@@ -0,0 +1,75 @@
+use std::f64::consts::TAU;
+
+use crate::{parse_complex, C64};
+
+/// A path through parameter space for the Julia constant to follow.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Path {
+    /// Once around the circle, counterclockwise from the point right of the
+    /// center, so the last frame leads back into the first
+    Circle { center: C64, radius: f64 },
+    /// Straight from one end to the other, both ends included
+    Line { from: C64, to: C64 },
+    /// Through each point in turn, spending the same number of frames on
+    /// every leg
+    List(Vec<C64>),
+}
+
+impl Path {
+    /// The constant for frame `i` of `frames`.
+    pub fn at(&self, i: u32, frames: u32) -> C64 {
+        /* Open paths end on their last point, the closed circle just short of it */
+        let t: f64 = match self {
+            Path::Circle { .. } => i as f64 / frames as f64,
+            _ => i as f64 / (frames - 1).max(1) as f64,
+        };
+        match self {
+            Path::Circle { center, radius } => center + C64::from_polar(*radius, TAU * t),
+            Path::Line { from, to } => from + (to - from) * t,
+            Path::List(points) => {
+                let legs = (points.len() - 1).max(1);
+                let along: f64 = t * legs as f64;
+                let leg = (along.floor() as usize).min(legs - 1);
+                let (a, b) = (points[leg], points[(leg + 1).min(points.len() - 1)]);
+                a + (b - a) * (along - leg as f64)
+            }
+        }
+    }
+}
+
+/// Parses `circle:CENTER,RADIUS`, `line:FROM,TO` or `list:C1,C2,...`, with
+/// points written like --julia, e.g. `circle:0,0.7885`.
+pub fn parse_path(s: &str) -> Result<Path, String> {
+    let Some((kind, rest)) = s.split_once(':') else {
+        return Err(format!(
+            "'{s}' is not a path such as circle:0,0.7885, line:-0.8,0.4i or list:-1,0.3+0.5i,i"
+        ));
+    };
+    let parts: Vec<&str> = rest.split(',').collect();
+    match (kind, parts.as_slice()) {
+        ("circle", [center, radius]) => {
+            let radius: f64 = radius
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid radius '{radius}': {e}"))?;
+            Ok(Path::Circle {
+                center: parse_complex(center)?,
+                radius,
+            })
+        }
+        ("circle", _) => Err(format!("'{s}' needs a center and a radius")),
+        ("line", [from, to]) => Ok(Path::Line {
+            from: parse_complex(from)?,
+            to: parse_complex(to)?,
+        }),
+        ("line", _) => Err(format!("'{s}' needs two ends")),
+        ("list", _) => parts
+            .iter()
+            .map(|point| parse_complex(point))
+            .collect::<Result<Vec<C64>, String>>()
+            .map(Path::List),
+        _ => Err(format!(
+            "unknown path '{kind}', expected circle, line or list"
+        )),
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{map_rows, RenderConfig, Viewport, C64};
+
+/* Samples per side of the grid searched at each step */
+const SEARCH_GRID: i32 = 33;
+/* Magnification from one step to the next */
+const ZOOM_STEP: f64 = 4.0;
+/* Stop once grid samples are this few doubles apart, where f64 runs out */
+const PRECISION_ULPS: f64 = 16.0;
+
+/// Zooms toward detail by following the steepest escape-count gradient. Each
+/// step samples a grid over the view, moves to the escaping sample whose
+/// counts differ most from its neighbors, which lies on the boundary where
+/// filaments and minibrots are, and zooms in by ZOOM_STEP. Starts from the
+/// configured viewport, or the full set under the original inverted framing.
+/// Returns the view after each step; fewer than `steps` if the grid turns flat
+/// or f64 precision runs out.
+pub fn find(config: &RenderConfig, steps: u32) -> Vec<Viewport> {
+    let mut viewport: Viewport = config.viewport.unwrap_or_default();
+    let mut path: Vec<Viewport> = Vec::new();
+    for _ in 0..steps {
+        let sample = RenderConfig {
+            width: SEARCH_GRID,
+            height: SEARCH_GRID,
+            viewport: Some(viewport),
+            ..config.clone()
+        };
+        let counts: Vec<Vec<i32>> =
+            map_rows(0..SEARCH_GRID, &sample, |c: C64| sample.escape(c).count);
+        let Some((x, y)) = steepest(&counts, config.max_iter) else {
+            break;
+        };
+        let point: C64 = sample.pixel_to_complex(x, y);
+        let next = Viewport {
+            center: sample.plane_point(point),
+            zoom: viewport.zoom * ZOOM_STEP,
+            ..viewport
+        };
+        let spacing: f64 = (sample.pixel_to_complex(x + 1, y) - point).norm() / ZOOM_STEP;
+        if spacing < PRECISION_ULPS * f64::EPSILON * next.center.norm().max(1.0) {
+            break;
+        }
+        path.push(next);
+        viewport = next;
+    }
+    path
+}
+
+/* Escaping grid point with the largest summed count difference to its four
+neighbors; ties go to the one nearest the middle. None on a flat grid */
+fn steepest(counts: &[Vec<i32>], max_iter: i32) -> Option<(i32, i32)> {
+    let middle = SEARCH_GRID / 2;
+    let mut best: Option<(i32, i32, (i32, i32))> = None;
+    for y in 1..SEARCH_GRID - 1 {
+        for x in 1..SEARCH_GRID - 1 {
+            let at = |dx: i32, dy: i32| counts[(y + dy) as usize][(x + dx) as usize];
+            if at(0, 0) >= max_iter {
+                continue;
+            }
+            let score: i32 = [(1, 0), (-1, 0), (0, 1), (0, -1)]
+                .iter()
+                .map(|&(dx, dy)| (at(dx, dy) - at(0, 0)).abs())
+                .sum();
+            let distance = (x - middle).abs() + (y - middle).abs();
+            let better = match best {
+                None => score > 0,
+                Some((best_score, best_distance, _)) => {
+                    score > best_score || (score == best_score && distance < best_distance)
+                }
+            };
+            if better {
+                best = Some((score, distance, (x, y)));
+            }
+        }
+    }
+    best.map(|(_, _, point)| point)
+}
@@ -0,0 +1,152 @@
+use image::{ImageBuffer, Rgb};
+use ordered_float::NotNan;
+
+use crate::{map_jobs, Palette, RenderConfig, DOWNSCALE_FILTER};
+
+/// A finished rectangle of the image. `pixels` is row-major and has
+/// `width * height` entries; (`x`, `y`) is its top-left pixel.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<Rgb<u8>>,
+}
+
+/// Splits the image into `tile_size` squares (smaller at the right and bottom
+/// edges), renders them in parallel unless the config is single-threaded and
+/// hands each one to `on_tile` as soon as it is done. `tile_size` must be at
+/// least 1.
+///
+/// `on_tile` runs on the thread that rendered the tile, so it may be
+/// called concurrently and tiles arrive in no particular order. Every tile is
+/// delivered exactly once before this returns.
+pub fn render_tiles<F>(config: &RenderConfig, palette: &Palette, tile_size: i32, on_tile: F)
+where
+    F: Fn(Tile) + Sync,
+{
+    render_tiles_while(config, palette, tile_size, || true, on_tile);
+}
+
+/// Like `render_tiles`, but checks `keep_going` before starting each tile and
+/// skips the rest once it returns false, so a render that is no longer wanted
+/// stops after the tiles already in progress.
+pub fn render_tiles_while<G, F>(
+    config: &RenderConfig,
+    palette: &Palette,
+    tile_size: i32,
+    keep_going: G,
+    on_tile: F,
+) where
+    G: Fn() -> bool + Sync,
+    F: Fn(Tile) + Sync,
+{
+    map_jobs(
+        origins(config.width, config.height, tile_size),
+        config,
+        |(x0, y0)| {
+            if !keep_going() {
+                return;
+            }
+            let width = tile_size.min(config.width - x0);
+            let height = tile_size.min(config.height - y0);
+            let pixels = render_region(config, palette, x0, y0, width, height);
+            on_tile(Tile {
+                x: x0,
+                y: y0,
+                width,
+                height,
+                pixels,
+            });
+        },
+    );
+}
+
+/// Like `render_tiles` for a render at `factor` times the output size in each
+/// direction that is downscaled with DOWNSCALE_FILTER, as --ssaa does.
+/// `config` has the render size; `tile_size` and the delivered tiles are in
+/// output pixels.
+///
+/// The filter reads pixels around each output pixel, so every tile renders a
+/// margin of `apron` output pixels beyond its bounds, wherever the image goes
+/// on, and drops it after downscaling. An apron at least as wide as the
+/// filter's reach makes the tiles match a downscale of the whole image.
+pub fn render_tiles_downscaled<F>(
+    config: &RenderConfig,
+    palette: &Palette,
+    tile_size: i32,
+    factor: i32,
+    apron: i32,
+    on_tile: F,
+) where
+    F: Fn(Tile) + Sync,
+{
+    let (out_width, out_height) = (config.width / factor, config.height / factor);
+    map_jobs(
+        origins(out_width, out_height, tile_size),
+        config,
+        |(x0, y0)| {
+            let width = tile_size.min(out_width - x0);
+            let height = tile_size.min(out_height - y0);
+            let (ax0, ay0) = ((x0 - apron).max(0), (y0 - apron).max(0));
+            let (ax1, ay1) = (
+                (x0 + width + apron).min(out_width),
+                (y0 + height + apron).min(out_height),
+            );
+            let (aw, ah) = (ax1 - ax0, ay1 - ay0);
+            let rendered = render_region(
+                config,
+                palette,
+                ax0 * factor,
+                ay0 * factor,
+                aw * factor,
+                ah * factor,
+            );
+            let large: ImageBuffer<Rgb<u8>, Vec<u8>> =
+                ImageBuffer::from_fn((aw * factor) as u32, (ah * factor) as u32, |x, y| {
+                    rendered[(y * (aw * factor) as u32 + x) as usize]
+                });
+            let small = image::imageops::resize(&large, aw as u32, ah as u32, DOWNSCALE_FILTER);
+            let pixels: Vec<Rgb<u8>> = (y0 - ay0..y0 - ay0 + height)
+                .flat_map(|y| (x0 - ax0..x0 - ax0 + width).map(move |x| (x, y)))
+                .map(|(x, y)| *small.get_pixel(x as u32, y as u32))
+                .collect();
+            on_tile(Tile {
+                x: x0,
+                y: y0,
+                width,
+                height,
+                pixels,
+            });
+        },
+    );
+}
+
+/* Top-left pixels of the `tile_size` squares covering a `width` x `height` image */
+fn origins(width: i32, height: i32, tile_size: i32) -> Vec<(i32, i32)> {
+    assert!(tile_size >= 1, "tiles need a size of at least 1 pixel");
+    (0..height)
+        .step_by(tile_size as usize)
+        .flat_map(|y| (0..width).step_by(tile_size as usize).map(move |x| (x, y)))
+        .collect()
+}
+
+/// Colors the `width` x `height` rectangle whose top-left pixel is (`x0`, `y0`),
+/// row-major, on the calling thread.
+pub fn render_region(
+    config: &RenderConfig,
+    palette: &Palette,
+    x0: i32,
+    y0: i32,
+    width: i32,
+    height: i32,
+) -> Vec<Rgb<u8>> {
+    (y0..y0 + height)
+        .flat_map(|y| (x0..x0 + width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let position = config.escape_position(config.pixel_to_complex(x, y));
+            palette.get_color(NotNan::try_from(position).unwrap())
+        })
+        .collect()
+}
@@ -0,0 +1,35 @@
+use std::time::{Duration, Instant};
+
+/// Wall-clock time per phase of a render, measured between calls to `lap`.
+pub struct Timings {
+    last: Instant,
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    pub fn start() -> Timings {
+        Timings {
+            last: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    /// Closes the current phase under `name` and starts the next one.
+    pub fn lap(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.phases.push((name, now - self.last));
+        self.last = now;
+    }
+
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    pub fn print(&self) {
+        let total: Duration = self.phases.iter().map(|(_, d)| *d).sum();
+        eprintln!("{:<20} {:>10}", "phase", "ms");
+        for (name, duration) in self.phases.iter().chain([&("total", total)]) {
+            eprintln!("{:<20} {:>10.1}", name, duration.as_secs_f64() * 1000.0);
+        }
+    }
+}